@@ -0,0 +1,40 @@
+#!/usr/bin/env python
+# Analyzes a single crash once the job containers are gone. Prints the
+# result to stdout, everything else goes to stderr.
+# Passed environment variables are the same as for schedule.py
+# Arguments:
+# - name of the crash in /work/crashes
+
+import asyncio
+import json
+import logging
+import sys
+
+from grpc_handler import LaunchConfig, ClusterFuzzAnalyzer, GdbAnalyzer
+from schedule import Broker
+
+logging.getLogger().setLevel(logging.INFO)
+
+async def analyze(broker, name):
+    launch_config = LaunchConfig(broker.env, broker.config)
+    crash_path = launch_config.crashes_path.joinpath(name)
+    if not crash_path.exists():
+        logging.error(f"Requested crash {crash_path} is not found")
+        exit(1)
+
+    return {
+        "clusterfuzz": await ClusterFuzzAnalyzer(launch_config).analyze(crash_path),
+        "gdb": await GdbAnalyzer(launch_config).analyze(crash_path),
+    }
+
+def main():
+    if len(sys.argv) != 2:
+        logging.error("Usage: analyze.py <crash name>")
+        exit(1)
+
+    broker = Broker()
+    result = asyncio.run(analyze(broker, sys.argv[1]))
+    print(json.dumps(result))
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,56 @@
+#!/usr/bin/env python
+# Minimizes the corpus of the job together with its findings, the result is
+# stored in the job directory as a corpus.zip-like archive
+# Passed environment variables are the same as for schedule.py
+# Arguments:
+# - name of the archive in /work
+
+import logging
+import os
+import shutil
+import subprocess
+import sys
+
+from pathlib import Path
+from zipfile import ZipFile, ZIP_DEFLATED
+
+from grpc_handler import LaunchConfig
+from schedule import Broker, MODE_FLAGS
+
+logging.getLogger().setLevel(logging.INFO)
+
+def main():
+    if len(sys.argv) != 2:
+        logging.error("Usage: cmin.py <archive name>")
+        exit(1)
+
+    # The broker unpacks the target and the corpus and seeds the findings
+    # into the input directory
+    broker = Broker()
+    launch_config = LaunchConfig(broker.env, broker.config)
+    in_dir = Path(launch_config.fuzz_dir).joinpath("in")
+    out_dir = Path(launch_config.fuzz_dir).joinpath("cmin")
+    shutil.rmtree(out_dir, ignore_errors = True)
+
+    # The target is run the way the fuzzers of the job run it
+    fuzzer = broker.env["fuzzer"]
+    cmd = ["afl-cmin", *MODE_FLAGS[fuzzer.get("mode") or "qemu"], "-i", str(in_dir), "-o", str(out_dir), "--", str(launch_config.test_path)]
+    if fuzzer.get("input_file"):
+        cmd.append("@@")
+    logging.info(f"Running {' '.join(cmd)}")
+
+    # Progress goes to stderr, the agent reports it to the server
+    cp = subprocess.run(cmd, stdout = sys.stderr, env = {**os.environ, **launch_config.env}, cwd = launch_config.fuzz_dir)
+    if cp.returncode != 0 or not out_dir.exists():
+        logging.error(f"afl-cmin exited with {cp.returncode}")
+        exit(1)
+
+    archive = Path("/work").joinpath(sys.argv[1])
+    with ZipFile(archive, "w", ZIP_DEFLATED) as zip_file:
+        for entry in out_dir.iterdir():
+            if entry.is_file():
+                zip_file.write(entry, f"in/{entry.name}")
+    os.chown(archive, 1000, 1000)
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,57 @@
+#!/usr/bin/env python
+# Minimizes a single crash, the result is stored beside the original crash
+# as <name>.min
+# Passed environment variables are the same as for schedule.py, plus:
+# - MINIMIZER: optional command template with {input}, {output}, {mode} and
+#   {target}, the mode flag and the @@ of the target follow FUZZER
+# Arguments:
+# - name of the crash in /work/crashes
+
+import logging
+import os
+import shlex
+import subprocess
+import sys
+
+from grpc_handler import LaunchConfig
+from schedule import Broker, MODE_FLAGS
+
+logging.getLogger().setLevel(logging.INFO)
+
+DEFAULT_MINIMIZER = "afl-tmin {mode} -i {input} -o {output} -- {target}"
+
+def main():
+    if len(sys.argv) != 2:
+        logging.error("Usage: minimize.py <crash name>")
+        exit(1)
+
+    broker = Broker()
+    launch_config = LaunchConfig(broker.env, broker.config)
+    crash_path = launch_config.crashes_path.joinpath(sys.argv[1])
+    if not crash_path.exists():
+        logging.error(f"Requested crash {crash_path} is not found")
+        exit(1)
+
+    output = crash_path.with_name(crash_path.name + ".min")
+    fuzzer = broker.env["fuzzer"]
+    target = shlex.quote(str(launch_config.test_path))
+    if fuzzer.get("input_file"):
+        target += " @@"
+    cmd = (os.environ.get("MINIMIZER") or DEFAULT_MINIMIZER).format(
+        input = shlex.quote(str(crash_path)),
+        output = shlex.quote(str(output)),
+        mode = " ".join(MODE_FLAGS[fuzzer.get("mode") or "qemu"]),
+        target = target,
+    )
+    logging.info(f"Running {cmd}")
+
+    # Progress goes to stderr, the agent reports it to the server
+    cp = subprocess.run(cmd, shell = True, stdout = sys.stderr, env = {**os.environ, **launch_config.env}, cwd = launch_config.fuzz_dir)
+    if cp.returncode != 0 or not output.exists():
+        logging.error(f"Minimizer exited with {cp.returncode}")
+        exit(1)
+
+    os.chown(output, 1000, 1000)
+
+if __name__ == "__main__":
+    main()
@@ -6,6 +6,8 @@
 # - CPUS
 # - TIMEOUT
 # - FUZZ_DIR
+# - SAP_ENV_<name>: environment of the job, overrides [ENV] of config.ini
+# - FUZZER: JSON of the fuzzer options of the job
 
 from pathlib import Path
 from zipfile import ZipFile
@@ -13,7 +15,9 @@ from configparser import ConfigParser, ExtendedInterpolation, BasicInterpolation
 from subprocess import Popen, run, DEVNULL
 from grpclib.server import Server, Stream
 
+import json
 import os
+import shlex
 import signal
 import asyncio
 import shutil
@@ -24,17 +28,34 @@ from grpc_handler import Processor
 
 logging.getLogger().setLevel(logging.INFO)
 
+MODE_FLAGS = {
+    "qemu": ["-Q"],
+    "frida": ["-O"],
+    "unicorn": ["-U"],
+    "instrumented": [],
+}
+
 class AFLInstance():
     count = 0
-    def __init__(self, config, fuzz_dir, guid, id = "0", master = False):
+    def __init__(self, config, fuzzer, fuzz_dir, guid, id = "0", master = False, schedule = None):
         self.master = master
         self.env = dict(config["ENV"].items())
         self.fuzz_dir = fuzz_dir
-        self.cmd = "afl-fuzz -i in -o out -Q {} {} -- {}/target".format(
-            f"-M master_{id}" if self.master else f"-S slave_{id}",
-            f"-T {id},guid:{guid}", # Tag injection vuln? lol
-            fuzz_dir
-        )
+
+        args = ["afl-fuzz", "-i", "in", "-o", "out", *MODE_FLAGS[fuzzer.get("mode") or "qemu"]]
+        args += ["-M", f"master_{id}"] if self.master else ["-S", f"slave_{id}"]
+        args += ["-T", f"{id},guid:{guid}"]
+        if schedule:
+            args += ["-p", schedule]
+        if fuzzer.get("dict"):
+            args += ["-x", str(Path(fuzz_dir).joinpath(fuzzer["dict"]))]
+        args += fuzzer.get("extra_args", [])
+        # The same binary the crash analysis runs, see grpc_handler.py
+        test_bin = config.has_section("LAUNCH") and config["LAUNCH"].get("SAP_TEST_BIN") or "target"
+        args += ["--", str(Path(fuzz_dir).joinpath(test_bin))]
+        if fuzzer.get("input_file"):
+            args.append("@@")
+        self.cmd = " ".join(shlex.quote(arg) for arg in args)
 
     async def start(self):
         self.popen = await asyncio.create_subprocess_shell(self.cmd, stdout = DEVNULL, stderr = DEVNULL, env = self.env, shell = True, cwd = self.fuzz_dir)
@@ -52,6 +73,7 @@ class Broker:
         Path(self.env["fuzz_dir"]).mkdir(parents = True, exist_ok = True)
         self.extract_files("/work/data/target.zip")
         self.extract_files("/work/data/corpus.zip")
+        self.seed_findings()
         self.parse_config()
         self.rc = 0
 
@@ -62,6 +84,7 @@ class Broker:
             "cpus": int(os.environ.get("CPUS")),
             "ram": os.environ.get("RAM"),
             "fuzz_dir": os.environ.get("FUZZ_DIR"),
+            "fuzzer": json.loads(os.environ.get("FUZZER") or "{}"),
         }
 
         if self.env["guid"] is None:
@@ -86,20 +109,39 @@ class Broker:
             logging.error(f"Failed to unzip {target}")
             exit(1)
 
+    def seed_findings(self):
+        # A restarted job continues from the findings of the previous runs
+        in_dir = Path(self.env["fuzz_dir"]).joinpath("in")
+        for queue in Path("/work/res").glob("*/queue"):
+            for entry in queue.iterdir():
+                if entry.is_file():
+                    shutil.copy(entry, in_dir.joinpath(f"{queue.parent.name}_{entry.name}"))
+
     def parse_config(self):
         self.config = ConfigParser(interpolation = ExtendedInterpolation())
         self.config.optionxform=str
         self.config.read(self.env["fuzz_dir"] + "/config.ini")
 
+        if not self.config.has_section("ENV"):
+            self.config.add_section("ENV")
+        for key, value in os.environ.items():
+            if key.startswith("SAP_ENV_"):
+                # Escaped for the interpolation of the config
+                self.config["ENV"][key[len("SAP_ENV_"):]] = value.replace("$", "$$")
+
     async def schedule_fuzzers(self):
         self.instances = []
 
-        x = AFLInstance(self.config, self.env["fuzz_dir"], self.env["guid"], self.env["id"], master = True)
+        fuzzer = self.env["fuzzer"]
+        x = AFLInstance(self.config, fuzzer, self.env["fuzz_dir"], self.env["guid"], self.env["id"], master = True)
         await x.start()
         self.instances.append(x)
 
+        # The secondary instances take the power schedules in turn
+        schedules = fuzzer.get("schedules") or [None]
         for x in range(1, self.env["cpus"]):
-            instance = AFLInstance(self.config, self.env["fuzz_dir"], self.env["guid"], self.env["id"] + str(x))
+            schedule = schedules[(x - 1) % len(schedules)]
+            instance = AFLInstance(self.config, fuzzer, self.env["fuzz_dir"], self.env["guid"], self.env["id"] + str(x), schedule = schedule)
             await instance.start()
             self.instances.append(instance)
 
@@ -0,0 +1,53 @@
+use std::{fs, io};
+
+use subtle::ConstantTimeEq;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
+
+use crate::config::CONFIG;
+
+pub fn tls_config() -> io::Result<Option<ServerTlsConfig>> {
+    let (cert, key) = match (&CONFIG.tls_cert, &CONFIG.tls_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => return Ok(None),
+    };
+
+    let identity = Identity::from_pem(fs::read(cert)?, fs::read(key)?);
+    let mut tls = ServerTlsConfig::new().identity(identity);
+    if let Some(ca) = &CONFIG.tls_client_ca {
+        tls = tls.client_ca_root(Certificate::from_pem(fs::read(ca)?));
+    }
+
+    Ok(Some(tls))
+}
+
+/// The token is compared in constant time, so it can't be guessed from the
+/// response times
+pub fn check_token(req: &Request<()>) -> Result<(), Box<Status>> {
+    let token = match &CONFIG.auth_token {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+
+    match req
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(value) if bool::from(value.as_bytes().ct_eq(token.as_bytes())) => Ok(()),
+        _ => Err(Box::new(Status::unauthenticated("Invalid auth token"))),
+    }
+}
+
+/// Rejects the requests without the auth token
+#[derive(Clone, Copy)]
+pub struct AuthInterceptor;
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, req: Request<()>) -> Result<Request<()>, Status> {
+        check_token(&req).map_err(|err| *err)?;
+        Ok(req)
+    }
+}
@@ -6,15 +6,62 @@ use serde::Deserialize;
 pub struct Config {
     pub sap_agent_listen: String,
     pub nfs_dir: String,
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+    pub auth_token: Option<String>,
+    /// Minimizer command template, see fuzz-image/scripts/minimize.py
+    pub minimizer: Option<String>,
+    /// The agent registers itself on the server once both are set
+    pub server_url: Option<String>,
+    pub join_token: Option<String>,
+    /// Endpoint the server connects to, SAP_AGENT_LISTEN over http by default
+    pub advertise_endpoint: Option<String>,
+    pub description: Option<String>,
+    /// Comma separated "key=value" labels reported on registration
+    pub labels: Option<String>,
+    /// Cores and RAM in KB kept for the agent, Docker and the OS. KB is the
+    /// unit of the RAM of the agents and the jobs everywhere
+    #[serde(default)]
+    pub reserved_cpus: u64,
+    #[serde(default)]
+    pub reserved_ram: u64,
+    /// Schedules on the logical cores instead of the physical ones
+    #[serde(default)]
+    pub logical_cores: bool,
+    /// The cores and RAM left after the reservation are multiplied by these.
+    /// The overcommit applies to scheduling, a container still gets the cpus
+    /// of its sub-job as the quota. Docker can't give it more than the host
+    /// cores, such a quota is capped and the job reports it
+    #[serde(default = "default_overcommit")]
+    pub cpu_overcommit: f64,
+    #[serde(default = "default_overcommit")]
+    pub ram_overcommit: f64,
+}
+
+fn default_state_dir() -> String {
+    "./state".to_string()
+}
+
+fn default_overcommit() -> f64 {
+    1.0
 }
 
 fn init_config() -> Config {
     dotenv().ok();
 
-    match envy::from_env::<Config>() {
+    let config = match envy::from_env::<Config>() {
         Ok(config) => config,
         Err(err) => panic!("Couldn't process env variables: {:#?}", err),
+    };
+
+    if config.cpu_overcommit <= 0.0 || config.ram_overcommit <= 0.0 {
+        panic!("CPU_OVERCOMMIT and RAM_OVERCOMMIT have to be positive");
     }
+
+    config
 }
 
 lazy_static! {
@@ -3,52 +3,71 @@ use std::{
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::config::CONFIG;
 use crate::jobs::Jobs;
+use crate::outbox::Outbox;
 use crate::protos::agent::CrashMsg;
+use crate::task_container::{job_env, Limits, TaskContainer};
 use bollard::{
     container::{
-        Config, CreateContainerOptions, InspectContainerOptions, LogsOptions, WaitContainerOptions,
+        Config, CreateContainerOptions, InspectContainerOptions, LogsOptions,
+        RemoveContainerOptions, StopContainerOptions, WaitContainerOptions,
     },
     errors::Error as BollardError,
     image::CreateImageOptions,
-    models::{ContainerWaitResponse, HostConfig},
+    models::ContainerWaitResponse,
     Docker,
 };
-use futures::stream;
-use futures_core::Stream;
-use log::{error, info};
-use tokio::{
-    sync::{mpsc::Sender, RwLock},
-    task, time,
-};
-use tokio_stream::StreamExt;
+use dashmap::DashMap;
+use futures::{future, stream};
+use futures_core::{Future, Stream};
+use log::{error, info, warn};
+use tokio::{sync::mpsc, task, time};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::{transport::Channel, Request, Response, Status};
 
 use crate::protos::agent::job_server::Job;
 use crate::protos::agent::{
-    update::UpdateKind, AnalyzeRequest, AnalyzeResponse, Empty, JobCreateRequest, JobGuid,
-    JobInfoContainerList, JobMsg, JobsList, Update,
+    update::UpdateKind, AnalyzeRequest, AnalyzeResponse, CorpusMinimizeRequest, CorpusMsg, Empty,
+    JobCreateRequest, JobGuid, JobInfoContainerList, JobMsg, JobsList, LogChunk, LogsRequest,
+    MinimizeMsg, MinimizeRequest,
 };
 use crate::protos::docker::process_client::ProcessClient;
 use crate::protos::docker::CrashAnalyzeRequest;
 
+/// Upper bound for a crash analysis made by a short-lived container
+const ANALYZE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Upper bound for a crash minimization
+const MINIMIZE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Upper bound for an afl-cmin pass over a corpus
+const CORPUS_MINIMIZE_TIMEOUT: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// How often the minimization progress is reported to the server
+const MINIMIZE_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Connections to the gRPC handlers of the live job containers
+type ProcessClients = DashMap<String, ProcessClient<Channel>>;
+
 #[derive(Debug)]
 pub struct JobHandler {
-    updates: Arc<RwLock<Option<Sender<Update>>>>,
+    updates: Arc<Outbox>,
     docker: Arc<Docker>,
     jobs: Arc<Jobs>,
+    clients: Arc<ProcessClients>,
 }
 
 impl JobHandler {
-    pub fn new(updates: Arc<RwLock<Option<Sender<Update>>>>, docker: Docker) -> JobHandler {
+    pub fn new(updates: Arc<Outbox>, jobs: Arc<Jobs>, docker: Docker) -> JobHandler {
         JobHandler {
             updates,
             docker: Arc::new(docker),
-            jobs: Arc::new(Jobs::new()),
+            jobs,
+            clients: Arc::new(DashMap::new()),
         }
     }
 }
@@ -57,7 +76,8 @@ struct JobItem {
     req: JobCreateRequest,
     docker: Arc<Docker>,
     jobs: Arc<Jobs>,
-    updates: Arc<RwLock<Option<Sender<Update>>>>,
+    updates: Arc<Outbox>,
+    clients: Arc<ProcessClients>,
     id: Option<String>,
     job_dir: PathBuf,
     docker_client: Option<ProcessClient<Channel>>,
@@ -68,7 +88,8 @@ impl JobItem {
         req: JobCreateRequest,
         docker: Arc<Docker>,
         jobs: Arc<Jobs>,
-        updates: Arc<RwLock<Option<Sender<Update>>>>,
+        updates: Arc<Outbox>,
+        clients: Arc<ProcessClients>,
     ) -> JobItem {
         let job_dir = Path::new(&CONFIG.nfs_dir).join("jobs").join(&req.job_guid);
 
@@ -77,6 +98,7 @@ impl JobItem {
             docker,
             jobs,
             updates,
+            clients,
             id: None,
             job_dir,
             docker_client: None,
@@ -84,13 +106,7 @@ impl JobItem {
     }
 
     async fn send_update(&self, kind: UpdateKind) {
-        if let Some(tx) = &*self.updates.read().await {
-            let _ = tx
-                .send(Update {
-                    update_kind: Some(kind),
-                })
-                .await;
-        }
+        self.updates.push(kind);
     }
 
     async fn get_logs(&self) -> Result<Option<String>, BollardError> {
@@ -140,23 +156,32 @@ impl JobItem {
         Ok(())
     }
 
+    fn limits(&self) -> Limits {
+        Limits {
+            cpus: self.req.cpus,
+            ram: self.req.ram,
+        }
+    }
+
     async fn create_container(&mut self) -> Result<(), BollardError> {
         let mount = vec![format!(
             "{}:/work",
             self.job_dir.to_string_lossy().into_owned()
         )];
+        let mut env = vec![
+            format!("GUID={}", self.req.job_guid),
+            format!("ID={}", self.req.idx),
+            format!("CPUS={}", self.req.cpus),
+            format!("RAM={}", self.req.ram),
+            format!("TIMEOUT={}", self.req.timeout),
+            "FUZZ_DIR=/root/fuzz".to_string(),
+        ];
+        env.extend(job_env(self.req.fuzzer.as_ref(), &self.req.env));
+
         let config = Config {
             image: Some(self.req.image.clone()),
-            host_config: Some(HostConfig {
-                binds: Some(mount),
-                ..Default::default()
-            }),
-            env: Some(vec![
-                format!("GUID={}", self.req.job_guid),
-                format!("ID={}", self.req.idx),
-                format!("CPUS={}", self.req.cpus),
-                "FUZZ_DIR=/root/fuzz".to_string(),
-            ]),
+            host_config: Some(self.limits().host_config(mount)),
+            env: Some(env),
             ..Default::default()
         };
 
@@ -176,10 +201,25 @@ impl JobItem {
 
         self.jobs.set_status(&self.req.job_guid, "alive");
 
+        // The overcommit stays visible, the sub-job keeps its cpus and only
+        // the quota of the container is capped
+        let last_msg = match self.limits().capped_cpus() {
+            Some(host_cpus) => {
+                warn!(
+                    "Job {} asks for {} cpus, its quota is capped at {} host cores",
+                    self.req.job_guid, self.req.cpus, host_cpus
+                );
+                format!(
+                    "Container started, {} overcommitted cpus share the {} host cores",
+                    self.req.cpus, host_cpus
+                )
+            }
+            None => "Container started".to_string(),
+        };
         self.send_update(UpdateKind::JobMsg(JobMsg {
             guid: self.req.job_guid.clone(),
             status: Some("alive".to_string()),
-            last_msg: Some("Container started".to_string()),
+            last_msg: Some(last_msg),
             log: None,
         }))
         .await;
@@ -201,26 +241,40 @@ impl JobItem {
             .ok_or("Couldn't get container ip address")?;
 
         tokio::time::sleep(Duration::from_secs(5)).await;
-        self.docker_client = Some(ProcessClient::connect(format!("http://{ip}:50051")).await?);
+        let client = ProcessClient::connect(format!("http://{ip}:50051")).await?;
+        self.clients
+            .insert(self.req.job_guid.clone(), client.clone());
+        self.docker_client = Some(client);
 
         Ok(())
     }
 
-    async fn handle_response(&self, response: ContainerWaitResponse) -> Result<(), BollardError> {
+    async fn handle_response(
+        &self,
+        response: ContainerWaitResponse,
+        timed_out: bool,
+    ) -> Result<(), BollardError> {
         let logs = self.get_logs().await?;
-        let status = if response.status_code == 0 {
-            "completed"
+        let (status, last_msg) = if timed_out {
+            (
+                "timeout",
+                format!("timeout of {} expired", self.req.timeout),
+            )
+        } else if response.status_code == 0 {
+            ("completed", "exited".to_string())
         } else {
-            "error"
+            ("error", "exited".to_string())
         };
+        // The registry entry is dropped once the server acks the update, so
+        // the final status has to be in place before the update is pushed
+        self.jobs.set_status(&self.req.job_guid, status);
         self.send_update(UpdateKind::JobMsg(JobMsg {
             guid: self.req.job_guid.clone(),
             status: Some(status.to_string()),
-            last_msg: Some("exited".to_string()),
+            last_msg: Some(last_msg),
             log: logs,
         }))
         .await;
-        self.jobs.set_status(&self.req.job_guid, status);
         Ok(())
     }
 
@@ -288,6 +342,48 @@ impl JobItem {
         Ok(())
     }
 
+    fn job_timeout(&self) -> Option<Duration> {
+        if self.req.timeout.is_empty() {
+            return None;
+        }
+
+        match self.req.timeout.parse::<humantime::Duration>() {
+            Ok(timeout) => Some(timeout.into()),
+            Err(err) => {
+                error!(
+                    "Ignoring invalid timeout {:?} for {}: {}",
+                    self.req.timeout, self.req.job_guid, err
+                );
+                None
+            }
+        }
+    }
+
+    async fn stop_on_timeout(&self) -> Result<(), BollardError> {
+        info!(
+            "Job {} reached its timeout of {}, stopping container",
+            self.req.job_guid, self.req.timeout
+        );
+
+        self.jobs
+            .set_last_msg(&self.req.job_guid, "Timeout expired".to_string());
+        self.send_update(UpdateKind::JobMsg(JobMsg {
+            guid: self.req.job_guid.clone(),
+            status: None,
+            last_msg: Some("Timeout expired, stopping container".to_string()),
+            log: None,
+        }))
+        .await;
+
+        // Give schedule.py enough time to sync the corpus before it gets killed
+        self.docker
+            .stop_container(
+                self.id.as_ref().unwrap(),
+                Some(StopContainerOptions { t: 60 }),
+            )
+            .await
+    }
+
     async fn wait_container(&mut self) -> Result<(), BollardError> {
         let mut stream = self.docker.wait_container(
             self.id.as_ref().unwrap(),
@@ -306,24 +402,40 @@ impl JobItem {
             Box::pin(stream::empty())
         };
 
+        let mut deadline: Pin<Box<dyn Future<Output = ()> + Send>> = match self.job_timeout() {
+            Some(timeout) => Box::pin(time::sleep(timeout)),
+            None => Box::pin(future::pending::<()>()),
+        };
+
+        let mut timed_out = false;
+        let mut exit_response = None;
+
         loop {
             tokio::select! {
                 Some(response) = stream.next() => {
                     let response = response?;
                     info!("Container exited: {:?}", response);
 
-                    self.handle_response(response).await?;
+                    exit_response = Some(response);
                     break;
                 },
                 Some(_) = sync_stream.next() => {
                     self.sync_crashes().await?;
                 },
+                _ = &mut deadline, if !timed_out => {
+                    timed_out = true;
+                    self.stop_on_timeout().await?;
+                },
                 else => break
             }
         }
 
         self.sync_crashes().await?;
 
+        if let Some(response) = exit_response {
+            self.handle_response(response, timed_out).await?;
+        }
+
         Ok(())
     }
 
@@ -353,23 +465,311 @@ impl JobItem {
         Ok(())
     }
 
+    async fn handle_reattach(
+        &mut self,
+        running: bool,
+    ) -> Result<(), Box<dyn error::Error + Send + Sync>> {
+        // Containers are named after the job guid
+        self.id = Some(self.req.job_guid.clone());
+        if running {
+            self.establish_connection().await?;
+        }
+        self.wait_container().await?;
+        self.remove_container().await?;
+        Ok(())
+    }
+
+    async fn handle_error(&mut self, err: Box<dyn error::Error + Send + Sync>) {
+        error!("{:#?}", err);
+        let logs = self.get_logs().await.ok().unwrap_or(None);
+        let err_msg = err.to_string();
+        self.jobs.set_status(&self.req.job_guid, "error");
+        self.jobs.set_last_msg(&self.req.job_guid, err_msg.clone());
+        self.send_update(UpdateKind::JobMsg(JobMsg {
+            guid: self.req.job_guid.clone(),
+            status: Some("error".to_string()),
+            last_msg: Some(err_msg),
+            log: logs,
+        }))
+        .await;
+
+        _ = self.force_stop().await;
+    }
+
     pub async fn main(&mut self) {
-        match self.handle().await {
-            Ok(_) => {}
+        if let Err(err) = self.handle().await {
+            self.handle_error(err).await;
+        }
+        self.clients.remove(&self.req.job_guid);
+    }
+
+    pub async fn reattach(&mut self, running: bool) {
+        if let Err(err) = self.handle_reattach(running).await {
+            self.handle_error(err).await;
+        }
+        self.clients.remove(&self.req.job_guid);
+    }
+}
+
+impl JobHandler {
+    /// Picks up the jobs which were alive when the agent went down and
+    /// reattaches to their containers
+    pub async fn recover(&self) {
+        self.jobs.forget_finished(&self.updates.pending_jobs());
+
+        for req in self.jobs.get_all() {
+            if req.status != "init" && req.status != "alive" && req.status != "paused" {
+                continue;
+            }
+
+            let guid = req.job_guid.clone();
+            let running = match self.docker.inspect_container(&guid, None).await {
+                Ok(container) => container
+                    .state
+                    .and_then(|state| state.running)
+                    .unwrap_or(false),
+                Err(err) => {
+                    error!("Container of job {} has been lost: {}", guid, err);
+                    self.report_status(
+                        &guid,
+                        "error",
+                        "Container has been lost while the agent was down",
+                    );
+                    continue;
+                }
+            };
+
+            if req.status == "init" && !running {
+                error!(
+                    "Job {} hasn't been started before the agent went down",
+                    guid
+                );
+                self.report_status(
+                    &guid,
+                    "error",
+                    "Agent went down before the container was started",
+                );
+                _ = self.docker.remove_container(&guid, None).await;
+                continue;
+            }
+
+            info!("Reattaching to job {} (running: {})", guid, running);
+            task::spawn({
+                let mut job_item = JobItem::new(
+                    req,
+                    self.docker.clone(),
+                    self.jobs.clone(),
+                    self.updates.clone(),
+                    self.clients.clone(),
+                );
+                async move {
+                    job_item.reattach(running).await;
+                }
+            });
+        }
+    }
+
+    fn report_status(&self, guid: &str, status: &str, last_msg: &str) {
+        self.jobs.set_status(guid, status);
+        self.jobs.set_last_msg(guid, last_msg.to_string());
+        self.updates.push(UpdateKind::JobMsg(JobMsg {
+            guid: guid.to_string(),
+            status: Some(status.to_string()),
+            last_msg: Some(last_msg.to_string()),
+            log: None,
+        }));
+    }
+
+    async fn analyze_live(&self, req: &AnalyzeRequest) -> Option<Result<String, Status>> {
+        let mut client = self.clients.get(&req.job_guid)?.clone();
+        let request = tonic::Request::new(CrashAnalyzeRequest {
+            name: req.name.clone(),
+        });
+        Some(
+            client
+                .analyze_crash(request)
+                .await
+                .map(|res| res.into_inner().result),
+        )
+    }
+
+    /// Analyzes the crash in a short-lived container started from the job
+    /// image, used once the job containers are gone
+    async fn analyze_in_container(
+        &self,
+        req: &AnalyzeRequest,
+    ) -> Result<String, Box<dyn error::Error + Send + Sync>> {
+        let container = TaskContainer::create(
+            self.docker.clone(),
+            format!("{}-analyze-{}", req.job_guid, req.crash_guid),
+            &req.image,
+            &req.job_guid,
+            vec![
+                "python".to_string(),
+                "/scripts/analyze.py".to_string(),
+                req.name.clone(),
+            ],
+            job_env(req.fuzzer.as_ref(), &req.env),
+            Limits {
+                cpus: req.cpus,
+                ram: req.ram,
+            },
+        )
+        .await?;
+
+        // analyze.py logs to stderr and prints only the result to stdout
+        let result = container.run(ANALYZE_TIMEOUT, |_| {}).await;
+        container.remove().await;
+
+        Ok(result?.trim().to_string())
+    }
+
+    /// Minimizes the crash in a short-lived container started from the job
+    /// image, the progress is reported through the updates
+    async fn minimize(docker: Arc<Docker>, updates: Arc<Outbox>, req: MinimizeRequest) {
+        let report = |status: &str, last_msg: String, minimized: Option<String>| {
+            updates.push(UpdateKind::MinimizeMsg(MinimizeMsg {
+                crash_guid: req.crash_guid.clone(),
+                status: status.to_string(),
+                last_msg,
+                minimized,
+            }));
+        };
+
+        report("running", "Starting minimizer".to_string(), None);
+        match Self::run_minimizer(docker, &req, |line| {
+            report("running", line.to_string(), None)
+        })
+        .await
+        {
+            Ok(minimized) => {
+                info!("Crash {} has been minimized", req.name);
+                report(
+                    "completed",
+                    "Crash has been minimized".to_string(),
+                    Some(minimized),
+                );
+            }
             Err(err) => {
-                error!("{:#?}", err);
-                let logs = self.get_logs().await.ok().unwrap_or(None);
-                let err_msg = err.to_string();
-                self.send_update(UpdateKind::JobMsg(JobMsg {
-                    guid: self.req.job_guid.clone(),
-                    status: Some("error".to_string()),
-                    last_msg: Some(err_msg),
-                    log: logs,
-                }))
-                .await;
-                self.jobs.set_status(&self.req.job_guid, "error");
+                error!("Failed to minimize crash {}: {}", req.name, err);
+                report("error", err.to_string(), None);
+            }
+        }
+    }
+
+    async fn run_minimizer(
+        docker: Arc<Docker>,
+        req: &MinimizeRequest,
+        on_progress: impl FnMut(&str),
+    ) -> Result<String, Box<dyn error::Error + Send + Sync>> {
+        let mut env = job_env(req.fuzzer.as_ref(), &req.env);
+        if let Some(minimizer) = &CONFIG.minimizer {
+            env.push(format!("MINIMIZER={}", minimizer));
+        }
+
+        let container = TaskContainer::create(
+            docker,
+            format!("{}-minimize-{}", req.job_guid, req.crash_guid),
+            &req.image,
+            &req.job_guid,
+            vec![
+                "python".to_string(),
+                "/scripts/minimize.py".to_string(),
+                req.name.clone(),
+            ],
+            env,
+            Limits {
+                cpus: req.cpus,
+                ram: req.ram,
+            },
+        )
+        .await?;
+
+        let result = container
+            .run(MINIMIZE_TIMEOUT, Self::throttle(on_progress))
+            .await;
+        container.remove().await;
+
+        result?;
+        Ok(format!("{}.min", req.name))
+    }
 
-                _ = self.force_stop().await;
+    /// The minimizers are chatty, passes their progress on once in a while
+    fn throttle(mut on_progress: impl FnMut(&str)) -> impl FnMut(&str) {
+        let mut last_report: Option<Instant> = None;
+        move |line| {
+            if line.trim().is_empty() {
+                return;
+            }
+            if let Some(last) = last_report {
+                if last.elapsed() < MINIMIZE_REPORT_INTERVAL {
+                    return;
+                }
+            }
+            last_report = Some(Instant::now());
+            on_progress(line);
+        }
+    }
+
+    /// Runs afl-cmin over the corpus and the findings of the job in a
+    /// short-lived container, the progress is reported through the updates
+    async fn minimize_corpus_task(
+        docker: Arc<Docker>,
+        updates: Arc<Outbox>,
+        req: CorpusMinimizeRequest,
+    ) {
+        let report = |status: &str, last_msg: String| {
+            updates.push(UpdateKind::CorpusMsg(CorpusMsg {
+                corpus_guid: req.corpus_guid.clone(),
+                status: status.to_string(),
+                last_msg,
+            }));
+        };
+
+        report("minimizing", "Starting afl-cmin".to_string());
+
+        let container = match TaskContainer::create(
+            docker,
+            format!("{}-cmin-{}", req.job_guid, req.corpus_guid),
+            &req.image,
+            &req.job_guid,
+            vec![
+                "python".to_string(),
+                "/scripts/cmin.py".to_string(),
+                format!("cmin-{}.zip", req.corpus_guid),
+            ],
+            job_env(req.fuzzer.as_ref(), &req.env),
+            Limits {
+                cpus: req.cpus,
+                ram: req.ram,
+            },
+        )
+        .await
+        {
+            Ok(container) => container,
+            Err(err) => {
+                error!("Failed to start afl-cmin for {}: {}", req.corpus_guid, err);
+                report("error", err.to_string());
+                return;
+            }
+        };
+
+        let result = container
+            .run(
+                CORPUS_MINIMIZE_TIMEOUT,
+                Self::throttle(|line| report("minimizing", line.to_string())),
+            )
+            .await;
+        container.remove().await;
+
+        match result {
+            Ok(_) => {
+                info!("Corpus {} has been minimized", req.corpus_guid);
+                report("ready", "Corpus has been minimized".to_string());
+            }
+            Err(err) => {
+                error!("Failed to minimize corpus {}: {}", req.corpus_guid, err);
+                report("error", err.to_string());
             }
         }
     }
@@ -389,6 +789,7 @@ impl Job for JobHandler {
                 self.docker.clone(),
                 self.jobs.clone(),
                 self.updates.clone(),
+                self.clients.clone(),
             );
             async move {
                 job_item.main().await;
@@ -403,6 +804,26 @@ impl Job for JobHandler {
 
         let req = request.into_inner();
 
+        // The job is being deleted on the server, running containers would keep
+        // writing into the removed job directory
+        if let Some(status) = self.jobs.get_status(&req.guid) {
+            if status == "alive" || status == "init" || status == "paused" {
+                if let Err(msg) = self
+                    .docker
+                    .remove_container(
+                        &req.guid,
+                        Some(RemoveContainerOptions {
+                            force: true,
+                            ..Default::default()
+                        }),
+                    )
+                    .await
+                {
+                    return Err(Status::invalid_argument(msg.to_string()));
+                }
+            }
+        }
+
         match self.jobs.destroy(&req.guid) {
             Some(_job) => Ok(Response::new(Empty {})),
             None => Err(Status::not_found(format!(
@@ -430,7 +851,13 @@ impl Job for JobHandler {
         let guid = request.into_inner().guid;
 
         if let Some(status) = self.jobs.get_status(&guid) {
-            if status == "alive" || status == "init" {
+            // Signals to a paused container are queued until it gets unpaused
+            if status == "paused" {
+                if let Err(msg) = self.docker.unpause_container(&guid).await {
+                    return Err(Status::invalid_argument(msg.to_string()));
+                }
+            }
+            if status == "alive" || status == "init" || status == "paused" {
                 match self.docker.stop_container(&guid, None).await {
                     Ok(_) => return Ok(Response::new(Empty {})),
                     Err(msg) => return Err(Status::invalid_argument(msg.to_string())),
@@ -441,12 +868,156 @@ impl Job for JobHandler {
         Err(Status::invalid_argument("Job not found"))
     }
 
+    async fn pause(&self, request: Request<JobGuid>) -> Result<Response<Empty>, Status> {
+        let guid = request.into_inner().guid;
+
+        match self.jobs.get_status(&guid) {
+            Some(status) if status == "alive" => {}
+            Some(_) => return Err(Status::failed_precondition("Job is not alive")),
+            None => return Err(Status::not_found("Job not found")),
+        }
+
+        if let Err(err) = self.docker.pause_container(&guid).await {
+            return Err(Status::internal(err.to_string()));
+        }
+        self.report_status(&guid, "paused", "Container paused");
+
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn resume(&self, request: Request<JobGuid>) -> Result<Response<Empty>, Status> {
+        let guid = request.into_inner().guid;
+
+        match self.jobs.get_status(&guid) {
+            Some(status) if status == "paused" => {}
+            Some(_) => return Err(Status::failed_precondition("Job is not paused")),
+            None => return Err(Status::not_found("Job not found")),
+        }
+
+        if let Err(err) = self.docker.unpause_container(&guid).await {
+            return Err(Status::internal(err.to_string()));
+        }
+        self.report_status(&guid, "alive", "Container resumed");
+
+        Ok(Response::new(Empty {}))
+    }
+
+    // tonic-build names the stream after the snake_case rpc
+    #[allow(non_camel_case_types)]
+    type logsStream = ReceiverStream<Result<LogChunk, Status>>;
+
+    async fn logs(
+        &self,
+        request: Request<LogsRequest>,
+    ) -> Result<Response<Self::logsStream>, Status> {
+        let LogsRequest { guid, idx } = request.into_inner();
+        match self.jobs.get(&guid) {
+            Some(job) if job.idx == idx => {
+                if !matches!(job.status.as_str(), "init" | "alive" | "paused") {
+                    return Err(Status::failed_precondition(format!(
+                        "Job {} isn't running",
+                        guid
+                    )));
+                }
+            }
+            _ => return Err(Status::not_found(format!("Job {} doesn't exist", guid))),
+        }
+
+        let (tx, rx) = mpsc::channel(100);
+        let docker = self.docker.clone();
+        task::spawn(async move {
+            let mut log_stream = docker.logs::<String>(
+                &guid,
+                Some(LogsOptions {
+                    follow: true,
+                    stdout: true,
+                    stderr: true,
+                    tail: "1000".to_string(),
+                    ..Default::default()
+                }),
+            );
+
+            loop {
+                tokio::select! {
+                    output = log_stream.next() => match output {
+                        Some(Ok(output)) => {
+                            let chunk = LogChunk {
+                                data: String::from_utf8_lossy(&output.into_bytes()).into_owned(),
+                            };
+                            if tx.send(Ok(chunk)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Some(Err(err)) => {
+                            _ = tx.send(Err(Status::internal(err.to_string()))).await;
+                            break;
+                        }
+                        None => break
+                    },
+                    _ = tx.closed() => break
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
     async fn analyze_crash(
         &self,
-        _request: Request<AnalyzeRequest>,
+        request: Request<AnalyzeRequest>,
     ) -> Result<Response<AnalyzeResponse>, Status> {
-        Ok(Response::new(AnalyzeResponse {
-            result: "".to_string(),
-        }))
+        let req = request.into_inner();
+        info!("Analyzing crash {} of job {}", req.name, req.job_guid);
+
+        match self.analyze_live(&req).await {
+            Some(Ok(result)) => return Ok(Response::new(AnalyzeResponse { result })),
+            Some(Err(err)) => info!(
+                "Job {} failed to analyze crash {}, starting an analysis container: {}",
+                req.job_guid, req.name, err
+            ),
+            None => {}
+        }
+
+        match self.analyze_in_container(&req).await {
+            Ok(result) => Ok(Response::new(AnalyzeResponse { result })),
+            Err(err) => Err(Status::internal(format!(
+                "Failed to analyze crash {}: {}",
+                req.name, err
+            ))),
+        }
+    }
+
+    async fn minimize_crash(
+        &self,
+        request: Request<MinimizeRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let req = request.into_inner();
+        info!("Minimizing crash {} of job {}", req.name, req.job_guid);
+
+        task::spawn(Self::minimize(
+            self.docker.clone(),
+            self.updates.clone(),
+            req,
+        ));
+
+        Ok(Response::new(Empty {}))
+    }
+    async fn minimize_corpus(
+        &self,
+        request: Request<CorpusMinimizeRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let req = request.into_inner();
+        info!(
+            "Minimizing corpus {} of job {}",
+            req.corpus_guid, req.job_guid
+        );
+
+        task::spawn(Self::minimize_corpus_task(
+            self.docker.clone(),
+            self.updates.clone(),
+            req,
+        ));
+
+        Ok(Response::new(Empty {}))
     }
 }
@@ -1,15 +1,63 @@
-use crate::protos::agent::JobCreateRequest;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::protos::agent::{JobCreateRequest, JobInfoContainerList};
 use dashmap::DashMap;
+use log::error;
+use prost::Message;
 
 #[derive(Debug)]
 pub struct Jobs {
     jobs: DashMap<String, JobCreateRequest>,
+    state_file: PathBuf,
+    persist_lock: Mutex<()>,
 }
 
 impl Jobs {
-    pub fn new() -> Jobs {
+    pub fn new(state_file: PathBuf) -> Jobs {
+        let jobs = DashMap::new();
+        match Self::load(&state_file) {
+            Ok(saved) => {
+                for job in saved {
+                    jobs.insert(job.job_guid.clone(), job);
+                }
+            }
+            Err(err) => error!("Failed to load jobs from {:?}: {}", state_file, err),
+        }
+
         Jobs {
-            jobs: DashMap::new(),
+            jobs,
+            state_file,
+            persist_lock: Mutex::new(()),
+        }
+    }
+
+    fn load(state_file: &Path) -> io::Result<Vec<JobCreateRequest>> {
+        match fs::read(state_file) {
+            Ok(buf) => JobInfoContainerList::decode(buf.as_slice())
+                .map(|list| list.jobs)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn persist(&self) {
+        let _guard = self.persist_lock.lock().unwrap();
+        let list = JobInfoContainerList {
+            jobs: self.get_all(),
+        };
+
+        // Write to a temporary file first, so a crash never leaves a truncated table
+        let tmp_file = self.state_file.with_extension("tmp");
+        let res = fs::write(&tmp_file, list.encode_to_vec())
+            .and_then(|_| fs::rename(&tmp_file, &self.state_file));
+        if let Err(err) = res {
+            error!("Failed to persist jobs to {:?}: {}", self.state_file, err);
         }
     }
 
@@ -19,28 +67,56 @@ impl Jobs {
 
     pub fn create(&self, req: JobCreateRequest) {
         self.jobs.insert(req.job_guid.clone(), req);
+        self.persist();
     }
 
     pub fn set_status(&self, guid: &str, status: &str) {
         if let Some(mut rt) = self.jobs.get_mut(guid) {
             rt.status = status.to_string();
+        } else {
+            return;
         }
+        self.persist();
     }
 
     pub fn set_last_msg(&self, guid: &str, message: String) {
         if let Some(mut rt) = self.jobs.get_mut(guid) {
             rt.last_msg = message;
+        } else {
+            return;
         }
+        self.persist();
     }
 
     pub fn destroy(&self, guid: &str) -> Option<(String, JobCreateRequest)> {
-        self.jobs.remove(guid)
+        let res = self.jobs.remove(guid);
+        if res.is_some() {
+            self.persist();
+        }
+        res
+    }
+
+    /// Drops the finished jobs whose final status isn't pending in the outbox
+    /// anymore, i.e. the server has already acknowledged it
+    pub fn forget_finished(&self, pending: &HashSet<String>) {
+        let before = self.jobs.len();
+        self.jobs.retain(|guid, job| {
+            !matches!(job.status.as_str(), "completed" | "error" | "timeout")
+                || pending.contains(guid)
+        });
+        if self.jobs.len() != before {
+            self.persist();
+        }
     }
 
     pub fn guids(&self) -> Vec<String> {
         self.jobs.iter().map(|k| k.key().clone()).collect()
     }
 
+    pub fn get(&self, guid: &str) -> Option<JobCreateRequest> {
+        Some(self.jobs.get(guid)?.clone())
+    }
+
     pub fn get_status(&self, guid: &str) -> Option<String> {
         Some(self.jobs.get(guid)?.status.clone())
     }
@@ -1,12 +1,14 @@
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 
 use bollard::Docker;
 use dotenv::dotenv;
 use log::info;
-use tokio::sync::mpsc::Sender;
-use tokio::sync::RwLock;
 use tonic::transport::Server;
 
+mod auth;
+
 mod config;
 use crate::config::CONFIG;
 
@@ -14,13 +16,21 @@ mod protos;
 use protos::agent::job_server::JobServer;
 use protos::agent::system_info_server::SystemInfoServer;
 use protos::agent::updates_server::UpdatesServer;
-use protos::agent::Update;
 
 mod jobs;
+use jobs::Jobs;
+
+mod outbox;
+use outbox::Outbox;
 
 mod job_handler;
 use job_handler::JobHandler;
 
+mod registration;
+use registration::Registration;
+
+mod task_container;
+
 mod system_info;
 use system_info::SystemInfoHandler;
 
@@ -33,19 +43,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     let docker = Docker::connect_with_socket_defaults().unwrap();
-    let tx: Arc<RwLock<Option<Sender<Update>>>> = Arc::new(RwLock::new(None));
+    fs::create_dir_all(&CONFIG.state_dir)?;
+    let outbox = Arc::new(Outbox::new(Path::new(&CONFIG.state_dir).to_path_buf()));
 
     let addr = CONFIG.sap_agent_listen.parse()?;
-    let job_handler = JobHandler::new(tx.clone(), docker);
+    let jobs = Arc::new(Jobs::new(Path::new(&CONFIG.state_dir).join("jobs.pb")));
+    let job_handler = JobHandler::new(outbox.clone(), jobs.clone(), docker);
+    job_handler.recover().await;
     let system_info_handler = SystemInfoHandler::new();
-    let updates_handler = UpdatesHandler::new(tx.clone());
+    let updates_handler = UpdatesHandler::new(outbox, jobs);
 
     info!("Listening on {}", CONFIG.sap_agent_listen);
 
-    Server::builder()
-        .add_service(JobServer::new(job_handler))
-        .add_service(SystemInfoServer::new(system_info_handler))
-        .add_service(UpdatesServer::new(updates_handler))
+    if let Some(registration) = Registration::from_config() {
+        tokio::spawn(registration.run());
+    }
+
+    let mut server = Server::builder();
+    if let Some(tls) = auth::tls_config()? {
+        info!("TLS is enabled");
+        server = server.tls_config(tls)?;
+    }
+
+    server
+        .add_service(JobServer::with_interceptor(
+            job_handler,
+            auth::AuthInterceptor,
+        ))
+        .add_service(SystemInfoServer::with_interceptor(
+            system_info_handler,
+            auth::AuthInterceptor,
+        ))
+        .add_service(UpdatesServer::with_interceptor(
+            updates_handler,
+            auth::AuthInterceptor,
+        ))
         .serve(addr)
         .await?;
 
@@ -0,0 +1,320 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{error, warn};
+use prost::Message;
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWriteExt, BufWriter},
+    sync::{mpsc, Notify},
+};
+
+use crate::protos::agent::{update::UpdateKind, Update};
+
+/// The log is rewritten once it grows past this size and twice the size it
+/// had after the last compaction, most of it are coalesced updates by then
+const COMPACT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Caps the updates kept while the server is away, the oldest ones are
+/// dropped beyond it
+const MAX_PENDING: usize = 100_000;
+
+#[derive(Debug)]
+struct OutboxState {
+    next_seq: u64,
+    pending: VecDeque<Update>,
+    /// Size of updates.log, including the updates which aren't pending anymore
+    log_size: usize,
+    compacted_size: usize,
+}
+
+#[derive(Debug)]
+enum LogWrite {
+    Append(Vec<u8>),
+    Rewrite { log: Vec<u8>, next_seq: u64 },
+}
+
+/// Durable log of the updates which haven't been acknowledged by the server yet.
+///
+/// Every update gets a sequence number and is appended to `updates.log` as a
+/// length-delimited protobuf message. The log is compacted once the server
+/// acknowledges a sequence number, the next sequence number is kept in
+/// `updates.seq` so it survives the compaction of the whole log.
+///
+/// The file is written by a background task which keeps it open, pushing an
+/// update never blocks on the disk. Progress updates only replace the pending
+/// one of the same job, crash or corpus.
+#[derive(Debug)]
+pub struct Outbox {
+    state: Mutex<OutboxState>,
+    notify: Notify,
+    writer: mpsc::UnboundedSender<LogWrite>,
+}
+
+impl Outbox {
+    pub fn new(state_dir: PathBuf) -> Outbox {
+        let log_file = state_dir.join("updates.log");
+        let seq_file = state_dir.join("updates.seq");
+
+        let (pending, log_size) = match Self::load(&log_file) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!("Failed to load updates from {:?}: {}", log_file, err);
+                (VecDeque::new(), 0)
+            }
+        };
+
+        let saved_seq = fs::read_to_string(&seq_file)
+            .ok()
+            .and_then(|seq| seq.trim().parse::<u64>().ok())
+            .unwrap_or(1);
+        let next_seq = pending
+            .back()
+            .map(|update| update.seq + 1)
+            .unwrap_or(1)
+            .max(saved_seq);
+
+        let (writer, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(Self::write_log(log_file, seq_file, receiver));
+
+        Outbox {
+            state: Mutex::new(OutboxState {
+                next_seq,
+                pending,
+                log_size,
+                compacted_size: log_size,
+            }),
+            notify: Notify::new(),
+            writer,
+        }
+    }
+
+    fn load(log_file: &Path) -> io::Result<(VecDeque<Update>, usize)> {
+        let buf = match fs::read(log_file) {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((VecDeque::new(), 0)),
+            Err(err) => return Err(err),
+        };
+
+        let mut pending = VecDeque::new();
+        let mut rest = buf.as_slice();
+        while !rest.is_empty() {
+            match Update::decode_length_delimited(&mut rest) {
+                Ok(update) => pending.push_back(update),
+                Err(err) => {
+                    // The tail of the log could be truncated by a crash
+                    error!("Dropping corrupted tail of {:?}: {}", log_file, err);
+                    break;
+                }
+            }
+        }
+
+        Ok((pending, buf.len()))
+    }
+
+    /// Applies the writes in order, the appends queued up in the meantime are
+    /// flushed together
+    async fn write_log(
+        log_file: PathBuf,
+        seq_file: PathBuf,
+        mut receiver: mpsc::UnboundedReceiver<LogWrite>,
+    ) {
+        let open = |log_file: PathBuf| async move {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .await?;
+            Ok::<_, io::Error>(BufWriter::new(file))
+        };
+
+        let mut file = match open(log_file.clone()).await {
+            Ok(file) => Some(file),
+            Err(err) => {
+                error!("Failed to open {:?}: {}", log_file, err);
+                None
+            }
+        };
+
+        let mut next = None;
+        loop {
+            let write = match next.take() {
+                Some(write) => write,
+                None => match receiver.recv().await {
+                    Some(write) => write,
+                    None => break,
+                },
+            };
+
+            let res = match write {
+                LogWrite::Append(buf) => match &mut file {
+                    Some(file) => {
+                        let mut res = file.write_all(&buf).await;
+                        loop {
+                            match receiver.try_recv() {
+                                Ok(LogWrite::Append(buf)) => {
+                                    res = res.and(file.write_all(&buf).await)
+                                }
+                                Ok(write) => {
+                                    next = Some(write);
+                                    break;
+                                }
+                                Err(_) => break,
+                            }
+                        }
+                        res.and(file.flush().await)
+                    }
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, "log isn't open")),
+                },
+                LogWrite::Rewrite { log, next_seq } => {
+                    // Every append has been flushed, the old handle can go
+                    file = None;
+                    let tmp_file = log_file.with_extension("tmp");
+                    let res = async {
+                        tokio::fs::write(&seq_file, next_seq.to_string()).await?;
+                        tokio::fs::write(&tmp_file, log).await?;
+                        tokio::fs::rename(&tmp_file, &log_file).await?;
+                        file = Some(open(log_file.clone()).await?);
+                        Ok::<_, io::Error>(())
+                    };
+                    res.await
+                }
+            };
+
+            if let Err(err) = res {
+                error!("Failed to write {:?}: {}", log_file, err);
+            }
+        }
+    }
+
+    /// Identifies the updates which only report progress, a newer one
+    /// replaces the pending one with the same key
+    fn progress_key(kind: &UpdateKind) -> Option<(&'static str, &str)> {
+        match kind {
+            UpdateKind::JobMsg(msg) if msg.status.is_none() && msg.log.is_none() => {
+                Some(("job", &msg.guid))
+            }
+            UpdateKind::MinimizeMsg(msg) if msg.status == "running" => {
+                Some(("minimize", &msg.crash_guid))
+            }
+            UpdateKind::CorpusMsg(msg) if msg.status == "minimizing" => {
+                Some(("corpus", &msg.corpus_guid))
+            }
+            _ => None,
+        }
+    }
+
+    fn compact(&self, state: &mut OutboxState) {
+        let mut log = Vec::new();
+        for update in state.pending.iter() {
+            log.extend(update.encode_length_delimited_to_vec());
+        }
+
+        state.log_size = log.len();
+        state.compacted_size = log.len();
+        _ = self.writer.send(LogWrite::Rewrite {
+            log,
+            next_seq: state.next_seq,
+        });
+    }
+
+    pub fn push(&self, kind: UpdateKind) {
+        {
+            let mut state = self.state.lock().unwrap();
+
+            if let Some(key) = Self::progress_key(&kind) {
+                state.pending.retain(|update| {
+                    update
+                        .update_kind
+                        .as_ref()
+                        .and_then(Self::progress_key)
+                        .map_or(true, |pending| pending != key)
+                });
+            }
+
+            let update = Update {
+                update_kind: Some(kind),
+                seq: state.next_seq,
+            };
+            state.next_seq += 1;
+
+            let buf = update.encode_length_delimited_to_vec();
+            state.log_size += buf.len();
+            _ = self.writer.send(LogWrite::Append(buf));
+            state.pending.push_back(update);
+
+            if state.pending.len() > MAX_PENDING {
+                if let Some(dropped) = state.pending.pop_front() {
+                    warn!("Outbox is full, dropping update {}", dropped.seq);
+                }
+            }
+
+            if state.log_size > COMPACT_SIZE.max(2 * state.compacted_size) {
+                self.compact(&mut state);
+            }
+        }
+
+        self.notify.notify_waiters();
+    }
+
+    /// Returns the pending updates with a sequence number greater than `seq`
+    pub fn since(&self, seq: u64) -> Vec<Update> {
+        let state = self.state.lock().unwrap();
+        state
+            .pending
+            .iter()
+            .filter(|update| update.seq > seq)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the sequence number the server should resume from. An agent
+    /// which lost its state can be behind the server, in that case everything
+    /// it has is replayed
+    pub fn resume_seq(&self, last_seq: u64) -> u64 {
+        let state = self.state.lock().unwrap();
+        if last_seq >= state.next_seq {
+            0
+        } else {
+            last_seq
+        }
+    }
+
+    /// Drops every update acknowledged by the server
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.pending.len();
+        while let Some(update) = state.pending.front() {
+            if update.seq > seq {
+                break;
+            }
+            state.pending.pop_front();
+        }
+
+        if state.pending.len() != before {
+            self.compact(&mut state);
+        }
+    }
+
+    /// Returns the guids of the jobs with a status update which hasn't been
+    /// acknowledged yet
+    pub fn pending_jobs(&self) -> HashSet<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .pending
+            .iter()
+            .filter_map(|update| match &update.update_kind {
+                Some(UpdateKind::JobMsg(msg)) if msg.status.is_some() => Some(msg.guid.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn notified(&self) -> tokio::sync::futures::Notified<'_> {
+        self.notify.notified()
+    }
+}
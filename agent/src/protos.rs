@@ -8,6 +8,8 @@ use agent::{
 #[allow(unused_imports)]
 use docker::{CrashAnalyzeRequest, CrashAnalyzeResponse};
 
+// The generated update kinds all end with Msg
+#[allow(clippy::enum_variant_names)]
 pub mod agent {
     tonic::include_proto!("agent");
 }
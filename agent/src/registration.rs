@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    error, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::config::CONFIG;
+use crate::system_info::SystemInfoHandler;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const SECRET_HEADER: &str = "X-Agent-Secret";
+
+#[derive(Serialize)]
+struct RegisterRequest {
+    guid: Option<String>,
+    secret: Option<String>,
+    description: String,
+    agent_type: String,
+    endpoint: String,
+    cpus: u64,
+    ram: u64,
+    reserved_cpus: u64,
+    reserved_ram: u64,
+    cpu_overcommit: f64,
+    ram_overcommit: f64,
+    labels: BTreeMap<String, String>,
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    guid: String,
+    #[serde(default)]
+    secret: Option<String>,
+}
+
+type Error = Box<dyn error::Error + Send + Sync>;
+
+/// Registers the agent on the server and keeps sending heartbeats. The guid
+/// assigned by the server is kept in STATE_DIR/agent_guid together with the
+/// secret proving the agent owns it in STATE_DIR/agent_secret, so the agent
+/// keeps its row and its jobs across restarts
+pub struct Registration {
+    client: Client,
+    server_url: String,
+    join_token: String,
+    sys_info: SystemInfoHandler,
+}
+
+impl Registration {
+    /// Returns None unless SERVER_URL and JOIN_TOKEN are set
+    pub fn from_config() -> Option<Registration> {
+        match (&CONFIG.server_url, &CONFIG.join_token) {
+            (Some(server_url), Some(join_token)) => Some(Registration {
+                client: Client::new(),
+                server_url: server_url.trim_end_matches('/').to_string(),
+                join_token: join_token.clone(),
+                sys_info: SystemInfoHandler::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn guid_file() -> PathBuf {
+        Path::new(&CONFIG.state_dir).join("agent_guid")
+    }
+
+    fn secret_file() -> PathBuf {
+        Path::new(&CONFIG.state_dir).join("agent_secret")
+    }
+
+    fn labels() -> BTreeMap<String, String> {
+        CONFIG
+            .labels
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|label| label.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .filter(|(key, _)| !key.is_empty())
+            .collect()
+    }
+
+    /// Returns None when the server refuses the stored guid
+    async fn register(&self, guid: Option<String>) -> Result<Option<String>, Error> {
+        let sys_info = self.sys_info.sys_info();
+        let secret = fs::read_to_string(Self::secret_file())
+            .ok()
+            .map(|secret| secret.trim().to_string());
+        let request = RegisterRequest {
+            guid,
+            secret,
+            description: CONFIG
+                .description
+                .clone()
+                .unwrap_or_else(|| CONFIG.sap_agent_listen.clone()),
+            agent_type: "linux".to_string(),
+            endpoint: CONFIG
+                .advertise_endpoint
+                .clone()
+                .unwrap_or_else(|| format!("http://{}", CONFIG.sap_agent_listen)),
+            cpus: sys_info.cpus,
+            ram: sys_info.ram,
+            reserved_cpus: sys_info.reserved_cpus,
+            reserved_ram: sys_info.reserved_ram,
+            cpu_overcommit: sys_info.cpu_overcommit,
+            ram_overcommit: sys_info.ram_overcommit,
+            labels: Self::labels(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/agent/register", self.server_url))
+            .bearer_auth(&self.join_token)
+            .json(&request)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::CONFLICT {
+            return Ok(None);
+        }
+        let response = response
+            .error_for_status()?
+            .json::<RegisterResponse>()
+            .await?;
+
+        if let Some(secret) = &response.secret {
+            fs::write(Self::secret_file(), secret)?;
+        }
+        fs::write(Self::guid_file(), &response.guid)?;
+        Ok(Some(response.guid))
+    }
+
+    /// Returns false when the server doesn't know the agent anymore. The
+    /// secret proves the heartbeat comes from the agent holding the guid
+    async fn heartbeat(&self, guid: &str) -> Result<bool, Error> {
+        let secret = fs::read_to_string(Self::secret_file())?;
+        let response = self
+            .client
+            .post(format!("{}/api/agent/{}/heartbeat", self.server_url, guid))
+            .bearer_auth(&self.join_token)
+            .header(SECRET_HEADER, secret.trim())
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    pub async fn run(self) {
+        let mut guid = fs::read_to_string(Self::guid_file())
+            .ok()
+            .map(|guid| guid.trim().to_string());
+
+        loop {
+            match self.register(guid.clone()).await {
+                Ok(Some(registered)) => {
+                    info!("Registered on {} as {}", self.server_url, registered);
+                    guid = Some(registered);
+                }
+                Ok(None) => {
+                    warn!(
+                        "The server refused the guid {}, registering as a new agent",
+                        guid.as_deref().unwrap_or_default()
+                    );
+                    guid = None;
+                    continue;
+                }
+                Err(err) => {
+                    error!("Failed to register on {}: {}", self.server_url, err);
+                    time::sleep(RETRY_INTERVAL).await;
+                    continue;
+                }
+            }
+
+            let mut interval = time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                match self.heartbeat(guid.as_deref().unwrap_or_default()).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!("The server has forgotten the agent, registering again");
+                        break;
+                    }
+                    Err(err) => error!("Failed to send a heartbeat: {}", err),
+                }
+            }
+        }
+    }
+}
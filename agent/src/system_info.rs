@@ -1,11 +1,18 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
 
-use sysinfo::{RefreshKind, System, SystemExt};
+use sysinfo::{DiskExt, RefreshKind, System, SystemExt};
 use tonic::{Request, Response, Status};
 
+use crate::config::CONFIG;
 use crate::protos::agent::system_info_server::SystemInfo;
 use crate::protos::agent::{Empty, SysInfo};
 
+// The generated update kinds all end with Msg
+#[allow(clippy::enum_variant_names)]
 pub mod agent {
     tonic::include_proto!("agent");
 }
@@ -19,25 +26,67 @@ impl SystemInfoHandler {
     pub fn new() -> SystemInfoHandler {
         SystemInfoHandler {
             sys: Arc::new(Mutex::new(System::new_with_specifics(
-                RefreshKind::new().with_memory(),
+                RefreshKind::new().with_memory().with_disks_list(),
             ))),
         }
     }
-}
 
-#[tonic::async_trait]
-impl SystemInfo for SystemInfoHandler {
-    async fn get(&self, _request: Request<Empty>) -> Result<Response<SysInfo>, Status> {
-        let sys = self.sys.clone();
-        let mut sys = sys.lock().unwrap();
+    /// Total and free space of the disk NFS_DIR is mounted from, the one
+    /// with the longest matching mount point
+    fn nfs_disk(sys: &System) -> (u64, u64) {
+        let nfs_dir = Path::new(&CONFIG.nfs_dir)
+            .canonicalize()
+            .unwrap_or_else(|_| Path::new(&CONFIG.nfs_dir).to_path_buf());
+
+        sys.disks()
+            .iter()
+            .filter(|disk| nfs_dir.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().components().count())
+            .map(|disk| (disk.total_space(), disk.available_space()))
+            .unwrap_or((0, 0))
+    }
+
+    /// Resources the server may schedule on, the host ones without the
+    /// reservation and multiplied by the overcommit ratio
+    fn schedulable(total: u64, reserved: u64, overcommit: f64) -> u64 {
+        (total.saturating_sub(reserved) as f64 * overcommit) as u64
+    }
+
+    pub fn sys_info(&self) -> SysInfo {
+        let mut sys = self.sys.lock().unwrap();
         sys.refresh_memory();
+        sys.refresh_disks_list();
+        let (disk_total, disk_free) = Self::nfs_disk(&sys);
 
         // There will be probably an overflow on 128 bit targets
-        let reply = SysInfo {
-            cpus: sys.physical_core_count().unwrap_or(0) as u64,
-            ram: sys.total_memory(),
-        };
+        let cores = if CONFIG.logical_cores {
+            thread::available_parallelism().map_or(0, |cores| cores.get())
+        } else {
+            sys.physical_core_count().unwrap_or(0)
+        } as u64;
+
+        SysInfo {
+            cpus: Self::schedulable(cores, CONFIG.reserved_cpus, CONFIG.cpu_overcommit),
+            ram: Self::schedulable(
+                sys.total_memory(),
+                CONFIG.reserved_ram,
+                CONFIG.ram_overcommit,
+            ),
+            load_avg: sys.load_average().one,
+            available_ram: sys.available_memory(),
+            disk_total,
+            disk_free,
+            reserved_cpus: CONFIG.reserved_cpus,
+            reserved_ram: CONFIG.reserved_ram,
+            cpu_overcommit: CONFIG.cpu_overcommit,
+            ram_overcommit: CONFIG.ram_overcommit,
+        }
+    }
+}
 
-        Ok(Response::new(reply))
+#[tonic::async_trait]
+impl SystemInfo for SystemInfoHandler {
+    async fn get(&self, _request: Request<Empty>) -> Result<Response<SysInfo>, Status> {
+        Ok(Response::new(self.sys_info()))
     }
 }
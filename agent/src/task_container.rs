@@ -0,0 +1,212 @@
+use std::{collections::HashMap, error, path::Path, sync::Arc, thread, time::Duration};
+
+use bollard::{
+    container::{
+        Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
+        WaitContainerOptions,
+    },
+    image::CreateImageOptions,
+    models::HostConfig,
+    Docker,
+};
+use tokio::time;
+use tokio_stream::StreamExt;
+
+use crate::config::CONFIG;
+use crate::protos::agent::FuzzerOptions;
+
+/// Resources of a container, the ram is in KB and 0 leaves it unbounded
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub cpus: u64,
+    pub ram: u64,
+}
+
+impl Limits {
+    /// Returns the host cores when the cpus are more than the host has. An
+    /// overcommitted agent can schedule such a sub-job, Docker rejects a
+    /// quota above the host cores though
+    pub fn capped_cpus(&self) -> Option<u64> {
+        let host_cpus = thread::available_parallelism().ok()?.get() as u64;
+        (self.cpus > host_cpus).then_some(host_cpus)
+    }
+
+    /// The host config of the job and the task containers, the same limits
+    /// apply to both so a task can't starve the fuzzers of the agent
+    pub fn host_config(&self, binds: Vec<String>) -> HostConfig {
+        let cpus = self.capped_cpus().unwrap_or(self.cpus);
+        let nano_cpus = i64::try_from(cpus)
+            .ok()
+            .and_then(|cpus| cpus.checked_mul(1_000_000_000))
+            .filter(|&nano_cpus| nano_cpus > 0);
+
+        // The ram is reported by sysinfo in KB, docker expects bytes. Swap is
+        // disabled by setting memory_swap to the same value as memory
+        let memory = i64::try_from(self.ram)
+            .ok()
+            .and_then(|ram| ram.checked_mul(1024))
+            .filter(|&memory| memory > 0);
+
+        HostConfig {
+            binds: Some(binds),
+            nano_cpus,
+            memory,
+            memory_swap: memory,
+            ..Default::default()
+        }
+    }
+}
+
+/// The options and the environment of the job as schedule.py reads them, the
+/// task scripts get the same to run the target the way the fuzzers do
+pub fn job_env(fuzzer: Option<&FuzzerOptions>, env: &HashMap<String, String>) -> Vec<String> {
+    let mut vars = Vec::new();
+    // Passed as JSON to keep the lists of the options intact
+    if let Some(fuzzer) = fuzzer {
+        let fuzzer = serde_json::json!({
+            "mode": fuzzer.mode,
+            "dict": fuzzer.dict,
+            "input_file": fuzzer.input_file,
+            "extra_args": fuzzer.extra_args,
+            "schedules": fuzzer.schedules,
+        });
+        vars.push(format!("FUZZER={}", fuzzer));
+    }
+    // Prefixed so schedule.py can tell the job environment apart
+    vars.extend(
+        env.iter()
+            .map(|(key, value)| format!("SAP_ENV_{}={}", key, value)),
+    );
+    vars
+}
+
+/// A short-lived container started from a job image to run a single script
+/// from fuzz-image/scripts against the job directory
+pub struct TaskContainer {
+    docker: Arc<Docker>,
+    id: String,
+}
+
+impl TaskContainer {
+    pub async fn create(
+        docker: Arc<Docker>,
+        name: String,
+        image: &str,
+        job_guid: &str,
+        cmd: Vec<String>,
+        mut env: Vec<String>,
+        limits: Limits,
+    ) -> Result<TaskContainer, Box<dyn error::Error + Send + Sync>> {
+        if image.is_empty() {
+            return Err("job image is not specified".into());
+        }
+
+        let mut pull = docker.create_image(
+            Some(CreateImageOptions {
+                from_image: image.to_string(),
+                ..Default::default()
+            }),
+            None,
+            None,
+        );
+        while let Some(state) = pull.next().await {
+            state?;
+        }
+
+        // The scripts expect the same environment as schedule.py
+        env.extend([
+            format!("GUID={}", job_guid),
+            "ID=task".to_string(),
+            "CPUS=1".to_string(),
+            "FUZZ_DIR=/root/fuzz".to_string(),
+        ]);
+
+        let job_dir = Path::new(&CONFIG.nfs_dir).join("jobs").join(job_guid);
+        let config = Config {
+            image: Some(image.to_string()),
+            cmd: Some(cmd),
+            host_config: Some(
+                limits.host_config(vec![format!("{}:/work", job_dir.to_string_lossy())]),
+            ),
+            env: Some(env),
+            ..Default::default()
+        };
+
+        let id = docker
+            .create_container(Some(CreateContainerOptions { name }), config)
+            .await?
+            .id;
+
+        Ok(TaskContainer { docker, id })
+    }
+
+    /// Runs the container to completion. Returns the stdout of the script,
+    /// every line of stderr is passed to on_progress as it comes
+    pub async fn run(
+        &self,
+        timeout: Duration,
+        mut on_progress: impl FnMut(&str),
+    ) -> Result<String, Box<dyn error::Error + Send + Sync>> {
+        self.docker
+            .start_container::<String>(&self.id, None)
+            .await?;
+
+        let mut log_stream = self.docker.logs::<String>(
+            &self.id,
+            Some(LogsOptions {
+                follow: true,
+                stdout: true,
+                stderr: true,
+                ..Default::default()
+            }),
+        );
+
+        let mut output = String::new();
+        let collect = async {
+            while let Some(chunk) = log_stream.next().await {
+                match chunk? {
+                    LogOutput::StdErr { message } => String::from_utf8_lossy(&message)
+                        .lines()
+                        .for_each(&mut on_progress),
+                    chunk => output += &String::from_utf8_lossy(&chunk.into_bytes()),
+                }
+            }
+            Ok::<(), bollard::errors::Error>(())
+        };
+
+        match time::timeout(timeout, collect).await {
+            Ok(res) => res?,
+            Err(_) => return Err("task timed out".into()),
+        }
+
+        let mut stream = self.docker.wait_container(
+            &self.id,
+            Some(WaitContainerOptions {
+                condition: "not-running",
+            }),
+        );
+        let response = match stream.next().await {
+            Some(response) => response?,
+            None => return Err("task container has vanished".into()),
+        };
+
+        if response.status_code != 0 {
+            return Err(format!("task exited with code {}", response.status_code).into());
+        }
+
+        Ok(output)
+    }
+
+    pub async fn remove(self) {
+        _ = self
+            .docker
+            .remove_container(
+                &self.id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await;
+    }
+}
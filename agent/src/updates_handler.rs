@@ -1,25 +1,32 @@
 use std::sync::Arc;
 
 use log::info;
-use tokio::sync::mpsc::{self, Sender};
-use tokio::sync::RwLock;
+use tokio::sync::mpsc;
 use tokio::task;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Code, Request, Response, Status};
+use tonic::{Request, Response, Status};
 
+use crate::jobs::Jobs;
+use crate::outbox::Outbox;
 use crate::protos::agent::updates_server::Updates;
-use crate::protos::agent::{Empty, Update};
+use crate::protos::agent::{Empty, Update, UpdatesAck, UpdatesRequest};
 
 #[derive(Debug)]
 pub struct UpdatesHandler {
-    events_sender: Arc<RwLock<Option<Sender<Update>>>>,
+    outbox: Arc<Outbox>,
+    jobs: Arc<Jobs>,
 }
 
 impl UpdatesHandler {
-    pub fn new(events: Arc<RwLock<Option<Sender<Update>>>>) -> UpdatesHandler {
-        UpdatesHandler {
-            events_sender: events,
-        }
+    pub fn new(outbox: Arc<Outbox>, jobs: Arc<Jobs>) -> UpdatesHandler {
+        UpdatesHandler { outbox, jobs }
+    }
+
+    /// Acknowledges the updates up to `seq` and forgets the finished jobs the
+    /// server knows about, so they aren't completed again on every sync
+    fn ack_seq(&self, seq: u64) {
+        self.outbox.ack(seq);
+        self.jobs.forget_finished(&self.outbox.pending_jobs());
     }
 }
 
@@ -27,49 +34,50 @@ impl UpdatesHandler {
 impl Updates for UpdatesHandler {
     type GetStream = ReceiverStream<Result<Update, Status>>;
 
-    async fn get(&self, req: Request<Empty>) -> Result<Response<Self::GetStream>, Status> {
+    async fn get(&self, req: Request<UpdatesRequest>) -> Result<Response<Self::GetStream>, Status> {
         let (tx, rx) = mpsc::channel(10);
 
-        let (txr, mut rxr) = mpsc::channel::<Update>(100);
-        {
-            let mut res = self.events_sender.write().await;
-            if res.is_some() {
-                return Err(Status::new(
-                    Code::Unavailable,
-                    "Agent has been already connected",
-                ));
-            } else {
-                *res = Some(txr);
-            }
-        }
-
         if let Some(addr) = req.remote_addr() {
             info!("[UpdatesHandler] Server {} connected!", addr);
         }
 
-        let events = self.events_sender.clone();
+        // Everything up to the requested sequence number has been processed,
+        // unless the agent lost its state and is behind the server
+        let requested_seq = req.into_inner().last_seq;
+        let mut last_seq = self.outbox.resume_seq(requested_seq);
+        if last_seq == requested_seq {
+            self.ack_seq(last_seq);
+        }
+        info!("[UpdatesHandler] Resuming from {}", last_seq);
+
+        let outbox = self.outbox.clone();
         task::spawn({
             async move {
-                loop {
-                    tokio::select! {
-                        event = rxr.recv() => match event {
-                            Some(event) => match tx.send(Ok(event)).await {
-                                Ok(_) => (),
-                                Err(_) => break
-                            },
-                            None => break
-                        },
-                        _ = tx.closed() => {
-                            break;
+                'outer: loop {
+                    // Subscribe before reading the outbox to not miss a push in between
+                    let notified = outbox.notified();
+
+                    for update in outbox.since(last_seq) {
+                        last_seq = update.seq;
+                        if tx.send(Ok(update)).await.is_err() {
+                            break 'outer;
                         }
                     }
+
+                    tokio::select! {
+                        _ = notified => (),
+                        _ = tx.closed() => break
+                    }
                 }
                 info!("[UpdatesHandler] Server disconnected");
-                let mut _res = events.write().await;
-                *_res = None;
             }
         });
 
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn ack(&self, req: Request<UpdatesAck>) -> Result<Response<Empty>, Status> {
+        self.ack_seq(req.into_inner().seq);
+        Ok(Response::new(Empty {}))
+    }
 }
@@ -13,6 +13,12 @@ function get_agent_icon(agent_type){
   }
 }
 
+function build_labels(labels){
+  return Object.entries(labels)
+    .map(([key, value]) => `<span class="badge badge-info ml-1">${sanitize(key)}=${sanitize(value)}</span>`)
+    .join("");
+}
+
 function build_agent_box(agent){
   return `
         <div class="col-md-3">
@@ -37,8 +43,24 @@ function build_agent_box(agent){
                     <span class="agent-badge float-right">${agent.guid}</span>
                   </li>
                   <li class="nav-item p-2">
-                    <i class="far fa-question-circle p-2 align-middle"></i> Last status
-                    <span class="agent-badge float-right">Image pulling</span>
+                    <i class="far fa-question-circle p-2 align-middle"></i> Status
+                    <span class="agent-badge float-right" title="${agent.status_changed ? 'Since ' + renderDate(agent.status_changed, 'display') : ''}">${sanitize(agent.status)}</span>
+                  </li>
+                  <li class="nav-item p-2">
+                    <i class="fas fa-tools p-2 align-middle"></i> State
+                    <select class="agent-state custom-select custom-select-sm w-auto float-right">
+                      ${["active", "draining", "maintenance"].map(state =>
+                        `<option value="${state}" ${agent.state == state ? "selected" : ""}>${state}</option>`
+                      ).join("")}
+                    </select>
+                  </li>
+                  <li class="nav-item p-2">
+                    <i class="far fa-clock p-2 align-middle"></i> Last seen
+                    <span class="agent-badge float-right">${agent.last_seen ? renderDate(agent.last_seen, 'display') : 'never'}</span>
+                  </li>
+                  <li class="nav-item p-2">
+                    <i class="fas fa-code-branch p-2 align-middle"></i> Version
+                    <span class="agent-badge float-right">${agent.version ? sanitize(agent.version) : 'unknown'}</span>
                   </li>
                   <li class="nav-item p-2">
                     <i class="fas fa-globe p-2 align-middle"></i> Endpoint
@@ -46,11 +68,28 @@ function build_agent_box(agent){
                   </li>
                   <li class="nav-item p-2">
                     <i class="fas fa-microchip p-2 align-middle"></i> CPUs
-                    <span class="agent-badge bg-primary float-right">${agent.free_cpus}/${agent.cpus}</span>
+                    <span class="agent-badge bg-primary float-right" title="${agent.reserved_cpus} reserved, ${agent.cpu_overcommit}x overcommit">${agent.free_cpus}/${agent.cpus}</span>
                   </li>
                   <li class="nav-item px-2 pt-2 pb-1">
                     <i class="fas fa-memory p-2 align-middle"></i> RAM
-                    <span class="agent-badge bg-primary float-right">${formatBytes(agent.free_ram * 1000)}/${formatBytes(agent.ram * 1000)}</span>
+                    <span class="agent-badge bg-primary float-right" title="${formatBytes(agent.reserved_ram * 1000)} reserved, ${agent.ram_overcommit}x overcommit">${formatBytes(agent.free_ram * 1000)}/${formatBytes(agent.ram * 1000)}</span>
+                  </li>
+                  <li class="nav-item px-2 pt-2 pb-1">
+                    <i class="fas fa-tachometer-alt p-2 align-middle"></i> Load
+                    <span class="agent-badge float-right" title="Available RAM ${agent.available_ram != null ? formatBytes(agent.available_ram * 1000) : 'unknown'}">${agent.load_avg != null ? agent.load_avg.toFixed(2) : 'unknown'}</span>
+                  </li>
+                  <li class="nav-item px-2 pt-2 pb-1">
+                    <i class="fas fa-hdd p-2 align-middle"></i> Disk
+                    <span class="agent-badge float-right">${agent.disk_total ? formatBytes(agent.disk_free) + '/' + formatBytes(agent.disk_total) : 'unknown'}</span>
+                  </li>
+                  ${agent.drift ? `
+                  <li class="nav-item px-2 pt-2 pb-1 text-warning" title="${agent.reconciled_at ? 'Found ' + renderDate(agent.reconciled_at, 'display') : ''}">
+                    <i class="fas fa-exclamation-triangle p-2 align-middle"></i> Drift
+                    <span class="float-right text-right">${sanitize(agent.drift)}</span>
+                  </li>` : ''}
+                  <li class="nav-item px-2 pt-2 pb-1">
+                    <i class="fas fa-tags p-2 align-middle"></i> Labels
+                    <span class="float-right">${build_labels(agent.labels)}</span>
                   </li>
                 </ul>
               </div>
@@ -105,6 +144,33 @@ async function delete_agent(guid){
   }
 }
 
+async function set_agent_state(guid, select){
+  try {
+    const response = await fetch(`/api/agent/${guid}/state`, {
+      method: "PUT",
+      headers: {
+        'Content-Type': 'application/json',
+      },
+      body: JSON.stringify({state: select.val()}),
+    });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    const result = await response.json();
+    // Draining an idle agent puts it straight into maintenance
+    select.val(result.state);
+    iziToast.success({
+      title: 'OK',
+      message: `Agent ${guid} is ${result.state} now`,
+    });
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
 async function init_agents(){
   const response = await fetch("/api/agents");
   const agents = await response.json();
@@ -117,6 +183,8 @@ async function init_agents(){
       var guid = button.data("agent-guid");
       confirm_agent_delection(guid);
     });
+    var state = agent_box.find(".agent-state").first();
+    state.change(() => set_agent_state(agent.guid, state));
     agent_box.appendTo("#content-panel");
   });
 }
@@ -0,0 +1,65 @@
+import 'datatables.net-bs4';
+import 'datatables.net-responsive-bs4';
+
+function init_crashes_table(crashes){
+  var t = $("#crashes-table").DataTable({
+    "responsive": true,
+    "autoWidth": false,
+    "data": crashes,
+    "columns": [
+      { "data": "guid" },
+      {
+        "data": "collection_guid",
+        "render": $.fn.dataTable.render.text()
+      },
+      {
+        "data": "creation_date",
+        "render": renderDate
+      },
+      {
+        "data": "name",
+        "render": $.fn.dataTable.render.text()
+      },
+    ],
+    "order": [[2, "desc"]]
+  });
+
+  $(t.table().container()).on("click", "tbody tr", function(){
+    var row = t.row(this);
+    window.location = "/crash/" + row.data().guid;
+  });
+}
+
+async function init_bucket_info(id){
+  try {
+    const response = await fetch(`/api/bucket/${id}`);
+    const data = await response.json();
+    const bucket = data.bucket;
+
+    var card = $("#bucket-info");
+    card.find("#type").text(bucket.crash_type);
+    card.find("#id").text(bucket.id);
+    card.find("#hits").text(bucket.hits);
+    card.find("#first-seen").text(new Date(bucket.first_seen).toLocaleString());
+    card.find("#last-seen").text(new Date(bucket.last_seen).toLocaleString());
+    card.find(".overlay").remove();
+
+    $("#signature").text(bucket.signature);
+
+    init_crashes_table(data.crashes);
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
+async function main(){
+  var id = window.location.pathname.split("/").pop();
+  await init_bucket_info(id);
+}
+
+(async() => {
+  await main()
+})();
@@ -0,0 +1,41 @@
+import 'datatables.net-bs4';
+import 'datatables.net-responsive-bs4';
+
+function main(){
+  var t = $("#buckets-table").DataTable({
+    "responsive": true,
+    "autoWidth": false,
+    "ajax": {
+      "url": `/api/buckets`,
+      "dataSrc": ""
+    },
+    "columns": [
+      { "data": "id" },
+      {
+        "data": "crash_type",
+        "render": $.fn.dataTable.render.text()
+      },
+      {
+        "data": "signature",
+        "render": $.fn.dataTable.render.text()
+      },
+      { "data": "hits" },
+      {
+        "data": "first_seen",
+        "render": renderDate
+      },
+      {
+        "data": "last_seen",
+        "render": renderDate
+      },
+    ],
+    "order": [[5, "desc"]]
+  });
+
+  $(t.table().container()).on("click", "tbody tr", function(){
+    var row = t.row(this);
+    window.location = "/bucket/" + row.data().id;
+  });
+}
+
+$(main);
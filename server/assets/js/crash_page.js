@@ -31,6 +31,95 @@ function handle_gdb(data){
   nav.removeClass("disabled");
 }
 
+function handle_analyzed(crash){
+  if (crash.analyzed != null) {
+    var analyzed = JSON.parse(crash.analyzed);
+    if (analyzed.clusterfuzz != null) {
+      handle_clusterfuzz(analyzed.clusterfuzz);
+    }
+    if (analyzed.gdb != null) {
+      handle_gdb(analyzed.gdb);
+    }
+  }
+}
+
+async function crash_analyze(guid){
+  var button = $("#analyze");
+  button.addClass("disabled");
+  button.find("b").text("Analyzing...");
+  try {
+    const response = await fetch(`/api/crash/${guid}/analyze`, { method: "POST" });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    const crash = await response.json();
+    handle_analyzed(crash);
+    iziToast.success({
+      title: 'OK',
+      message: 'Crash has been analyzed!',
+    });
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+  button.find("b").text("Analyze");
+  button.removeClass("disabled");
+}
+
+function handle_minimize(crash){
+  var status = crash.minimize_status || "no";
+  var badge = $("#minimize-status");
+  badge.text(status);
+  badge.toggleClass("bg-success", status == "completed");
+  badge.toggleClass("bg-danger", status == "error");
+  badge.toggleClass("bg-warning", status == "running");
+  $("#minimize-msg").text(crash.minimize_msg || "");
+
+  if (crash.minimized != null) {
+    var download = $("#download-minimized");
+    download.attr("href", `/api/crash/${crash.guid}/minimized`);
+    download.show();
+  }
+
+  $("#minimize").toggleClass("disabled", status == "running");
+  if (status == "running") {
+    setTimeout(() => poll_minimize(crash.guid), 5000);
+  }
+}
+
+async function poll_minimize(guid){
+  try {
+    const response = await fetch(`/api/crash/${guid}`);
+    handle_minimize(await response.json());
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
+async function crash_minimize(guid){
+  try {
+    const response = await fetch(`/api/crash/${guid}/minimize`, { method: "POST" });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    iziToast.success({
+      title: 'OK',
+      message: 'Minimization has been requested!',
+    });
+    await poll_minimize(guid);
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
 async function init_crash_info(guid){
   try {
     const response = await fetch(`/api/crash/${guid}`);
@@ -45,17 +134,24 @@ async function init_crash_info(guid){
     card.find("#size").text(formatBytes(crash.size));
     card.find(".overlay").remove();
 
+    var analyze = card.find("#analyze");
+    analyze.click(async function(event){
+      event.preventDefault();
+      await crash_analyze(guid);
+    });
+    analyze.show();
+
+    var minimize = card.find("#minimize");
+    minimize.click(async function(event){
+      event.preventDefault();
+      await crash_minimize(guid);
+    });
+    minimize.show();
+    handle_minimize(crash);
+
     $("#crash-hash").text(`sha256 - ${crash.hash}`);
 
-    if (crash.analyzed != null) {
-      var analyzed = JSON.parse(crash.analyzed);
-      if (analyzed.clusterfuzz !== null) {
-        handle_clusterfuzz(analyzed.clusterfuzz);
-      }
-      if (analyzed.gdb != null) {
-        handle_gdb(analyzed.gdb);
-      }
-    }
+    handle_analyzed(crash);
   } catch (err) {
     iziToast.error({
       title: 'Error',
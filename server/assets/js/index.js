@@ -31,7 +31,7 @@ async function init_crash_stats(){
   const response = await fetch("/api/crash");
   const stats = await response.json();
 
-  $("#crashes_total h3").text(stats.total);
+  $("#crashes_total h3").text(`${stats.total} / ${stats.unique}`);
   $("#crashes_total .overlay").remove();
 }
 
@@ -55,6 +55,11 @@ function build_aa_info(job){
         <h4>Agent last message</h4>
         <pre>${job.last_msg || 'No message received'}</pre>
       </div>
+      ${job.status == 'alive' ? `
+      <div>
+        <h4>Live log</h4>
+        <pre id="live-log-${job.idx}" class="live-log"></pre>
+      </div>` : ''}
       <div>
         <h4>Container logs</h4>
         <pre>${job.log || "No log received"}</pre>
@@ -79,6 +84,121 @@ async function job_stop(guid) {
   }
 }
 
+// The validation errors come as a JSON list of the failing fields
+async function error_message(response) {
+  const text = await response.text();
+  try {
+    return JSON.parse(text).errors.map(err => `${err.field}: ${err.message}`).join("<br>");
+  } catch (err) {
+    return text;
+  }
+}
+
+async function job_action(guid, action, message) {
+  try {
+    const response = await fetch(`/api/job/${guid}/${action}`, {
+      method: "POST",
+      headers: {
+        'Content-Type': 'application/json',
+      },
+      body: JSON.stringify({}),
+    });
+    if (!response.ok) {
+      throw new Error(await error_message(response));
+    }
+    iziToast.success({
+      title: 'OK',
+      message: message,
+    });
+    return await response.text();
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
+async function job_delete(guid, running) {
+  if (!confirm(running ? "The job is still running, stop and delete it?" : "Delete the job?")) {
+    return;
+  }
+  const keep_crashes = confirm("Keep the crash files in the archive?");
+
+  try {
+    const response = await fetch(`/api/job/${guid}?force=${running}&keep_crashes=${keep_crashes}`, {
+      method: "DELETE",
+    });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    window.location = "/jobs";
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
+async function job_save_corpus(guid) {
+  const name = prompt("Name of the corpus");
+  if (!name) {
+    return;
+  }
+  const minimize = confirm("Minimize the corpus with afl-cmin first?");
+
+  try {
+    const response = await fetch(`/api/job/${guid}/corpus`, {
+      method: "POST",
+      headers: {
+        'Content-Type': 'application/json',
+      },
+      body: JSON.stringify({name: name, minimize: minimize}),
+    });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    iziToast.success({
+      title: 'OK',
+      message: minimize ? `Corpus ${name} is being minimized` : `Corpus ${name} has been saved`,
+    });
+  } catch (err) {
+    iziToast.error({
+      title: 'Error',
+      message: err.message,
+    });
+  }
+}
+
+function bind_action(card, id, handler){
+  var button = card.find(`#${id}`);
+  button.click(async function(event){
+    event.preventDefault();
+    await handler();
+  });
+  button.show();
+}
+
+function follow_log(guid, idx){
+  const max_lines = 1000;
+  var log = $(`#live-log-${idx}`);
+  var source = new EventSource(`/api/job/${guid}/log/stream?idx=${idx}`);
+
+  source.onmessage = function(event){
+    var lines = (log.text() + event.data + "\n").split("\n");
+    log.text(lines.slice(-max_lines).join("\n"));
+    log.scrollTop(log.prop("scrollHeight"));
+  };
+  source.addEventListener("log-error", function(event){
+    log.text(log.text() + `[log stream failed: ${event.data}]\n`);
+    source.close();
+  });
+  source.onerror = function(){
+    source.close();
+  };
+}
+
 function init_crash_table(guid){
   var t = $("#crash-table").DataTable({
     "responsive": true,
@@ -116,18 +236,47 @@ async function init_job_info(guid){
     card.find("#description").text(job.job_collection.description);
     card.find("#guid").text(job.job_collection.guid);
     card.find("#created").text(job.job_collection.creation_date);
+    card.find("#created-by").text(job.job_collection.created_by);
     card.find("#cpus").text(job.job_collection.cpus);
-    card.find("#ram").text(job.job_collection.ram);
+    card.find("#ram").text(job.job_collection.ram ? `${job.job_collection.ram} KB` : "unbounded");
     card.find("#timeout").text(job.job_collection.timeout);
+    var fuzzer = job.job_collection.fuzzer;
+    card.find("#fuzzer-mode").text(fuzzer.mode);
+    card.find("#fuzzer-input").text(fuzzer["input-file"] ? "file" : "stdin");
+    card.find("#fuzzer-dict").text(fuzzer.dict);
+    card.find(".fuzzer-dict").toggle(fuzzer.dict.length > 0);
+    card.find("#fuzzer-schedules").text(fuzzer.schedules.join(", "));
+    card.find(".fuzzer-schedules").toggle(fuzzer.schedules.length > 0);
+    card.find("#fuzzer-args").text(fuzzer["extra-args"].join(" "));
+    card.find(".fuzzer-args").toggle(fuzzer["extra-args"].length > 0);
     card.find("#status").text(job.job_collection.status);
-    if (job.job_collection.status == "alive" || job.job_collection.status == "init") {
-      var stop = card.find("#stop");
-      stop.click(async function(event){
-        await job_stop(guid);
-        event.preventDefault();
-      });
-      stop.show();
+    var status = job.job_collection.status;
+    if (status == "queued") {
+      card.find("#status").text(`queued #${job.job_collection.queue_position}`);
+    }
+    if (status == "alive" || status == "init" || status == "paused" || status == "queued") {
+      bind_action(card, "stop", () => job_stop(guid));
+    }
+    if (status == "alive") {
+      bind_action(card, "pause", () => job_action(guid, "pause", "Job pause request sent!"));
+    }
+    if (status == "paused") {
+      bind_action(card, "resume", () => job_action(guid, "resume", "Job resume request sent!"));
     }
+    if (status == "completed" || status == "error" || status == "cancelled") {
+      bind_action(card, "restart", () => job_action(guid, "restart", "Job has been restarted!"));
+    }
+    bind_action(card, "clone", async () => {
+      const clone = await job_action(guid, "clone", "Job has been cloned!");
+      if (clone) {
+        window.location = `/job/${clone}`;
+      }
+    });
+    bind_action(card, "download-corpus", async () => {
+      window.location = `/api/job/${guid}/corpus`;
+    });
+    bind_action(card, "save-corpus", () => job_save_corpus(guid));
+    bind_action(card, "delete", () => job_delete(guid, status == "alive" || status == "init" || status == "paused"));
     card.find(".overlay").remove();
 
     var aa_navs = $("#assigned-agents-navs")
@@ -139,6 +288,9 @@ async function init_job_info(guid){
 
       var tab = $(build_aa_info(job));
       tab.appendTo(aa_tabs);
+
+      if (job.status == "alive")
+        follow_log(guid, job.idx);
     });
   } catch (err) {
     iziToast.error({
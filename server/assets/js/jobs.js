@@ -24,9 +24,10 @@ function renderAgentType(data, type) {
   return data;
 }
 
-function renderJobStatus(data, type) {
+function renderJobStatus(data, type, row) {
   if (type === "display") {
     var badge = "badge-secondary";
+    var text = data;
 
     switch (data){
       case "init":
@@ -34,13 +35,21 @@ function renderJobStatus(data, type) {
         badge = "badge-primary";
         break;
       case "completed":
+      case "timeout":
         badge = "badge-success";
         break;
       case "error":
         badge = "badge-danger";
         break;
+      case "paused":
+        badge = "badge-warning";
+        break;
+      case "queued":
+        badge = "badge-info";
+        text = `queued #${row.queue_position}`;
+        break;
     }
-    return `<span class="badge ${badge}">${data}</span>`;
+    return `<span class="badge ${badge}">${text}</span>`;
   }
   return data;
 }
@@ -0,0 +1,27 @@
+function main(){
+  $("#login-form").submit(async function(event){
+    event.preventDefault();
+
+    const response = await fetch("/api/login", {
+      method: "POST",
+      headers: {
+        'Content-Type': 'application/json',
+      },
+      body: JSON.stringify({
+        "username": $("#username").val(),
+        "password": $("#password").val()
+      }),
+    });
+
+    if (response.ok) {
+      window.location = "/";
+    } else {
+      iziToast.error({
+        title: 'Error',
+        message: await response.text(),
+      });
+    }
+  });
+}
+
+$(main);
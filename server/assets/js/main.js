@@ -59,6 +59,12 @@ function setup_modals(){
     var description = $(modal).find("#description").first().val();
     var agent_type = $(modal).find("#agent-type").first().val();
     var endpoint = $(modal).find("#endpoint").first().val();
+    var labels = {};
+    $(modal).find("#agent-labels").first().val().split(",").forEach(label => {
+      var [key, value] = label.split("=").map(part => part.trim());
+      if (key)
+        labels[key] = value || "";
+    });
 
     $.ajax({
       url: "/api/agent",
@@ -66,7 +72,8 @@ function setup_modals(){
       data: JSON.stringify({
         "description": description,
         "agent_type": agent_type,
-        "endpoint": endpoint
+        "endpoint": endpoint,
+        "labels": labels
       }),
       contentType:"application/json; charset=utf-8",
       success: function(agent, textStatus){
@@ -101,8 +108,20 @@ function setup_modals(){
     var cpus = modal.find("#cpus").first().val();
     var ram = modal.find("#ram").first().val();
     var timeout = modal.find("#timeout").first().val();
+    var priority = modal.find("#priority").first().val();
+    var strategy = modal.find("#strategy").first().val();
+    var min_cores = modal.find("#min-cores").first().val();
+    var pinned_agents = modal.find("#pinned-agents").first().val();
+    var constraints = modal.find("#constraints").first().val();
     var target = modal.find("#upload-target")[0].files[0];
     var corpus = modal.find("#upload-corpus")[0].files[0];
+    var corpus_name = modal.find("#corpus-name").first().val();
+    var corpus_job = modal.find("#corpus-job").first().val();
+    var fuzzer_mode = modal.find("#fuzzer-mode").first().val();
+    var fuzzer_input = modal.find("#fuzzer-input").first().val();
+    var fuzzer_dict = modal.find("#fuzzer-dict").first().val();
+    var fuzzer_schedules = modal.find("#fuzzer-schedules").first().val();
+    var fuzzer_args = modal.find("#fuzzer-args").first().val();
     var crash_auto_analyze = modal.find("#crash-auto-analyze").is(":checked");
     var trim_corpus = modal.find("#trim-corpus").is(":checked");
 
@@ -119,10 +138,31 @@ function setup_modals(){
       fd.append("ram", ram);
     if (timeout.length)
       fd.append("timeout", timeout);
+    if (priority.length)
+      fd.append("priority", priority);
+    fd.append("strategy", strategy);
+    if (min_cores.length)
+      fd.append("min-cores", min_cores);
+    if (strategy == "pin")
+      fd.append("agents", pinned_agents);
+    if (constraints.length)
+      fd.append("constraints", constraints);
     if (target)
       fd.append("target", target);
     if (corpus)
       fd.append("corpus", corpus);
+    if (corpus_name.length)
+      fd.append("corpus-name", corpus_name);
+    if (corpus_job.length)
+      fd.append("corpus-job", corpus_job);
+    fd.append("fuzzer-mode", fuzzer_mode);
+    fd.append("fuzzer-input", fuzzer_input);
+    if (fuzzer_dict.length)
+      fd.append("fuzzer-dict", fuzzer_dict);
+    if (fuzzer_schedules.length)
+      fd.append("fuzzer-schedules", fuzzer_schedules);
+    if (fuzzer_args.length)
+      fd.append("fuzzer-args", fuzzer_args);
     fd.append("crash-auto-analyze", crash_auto_analyze);
 
     $.ajax({
@@ -140,9 +180,12 @@ function setup_modals(){
         });
       },
       error: function(errMsg) {
+        var message = errMsg.responseText;
+        if (errMsg.responseJSON && errMsg.responseJSON.errors)
+          message = errMsg.responseJSON.errors.map(err => `${err.field}: ${err.message}`).join("<br>");
         iziToast.error({
           title: errMsg.statusText,
-          message: errMsg.responseText
+          message: message
         });
       }
     });
@@ -169,7 +212,33 @@ function main(){
     }
   });
 
+  $("#modal-add-job").on("show.bs.modal", async function(){
+    var select = $(this).find("#corpus-name");
+    const response = await fetch("/api/corpora");
+    if (!response.ok)
+      return;
+    const corpora = await response.json();
+    select.find("option[value!='']").remove();
+    corpora.filter(corpus => corpus.status == "ready").forEach(corpus => {
+      $("<option>").val(corpus.guid).text(corpus.name).appendTo(select);
+    });
+  });
+
+  $("#strategy").change(function(){
+    if(this.value == "pin") {
+      $(".pinned-agents").show("scale");
+    } else {
+      $(".pinned-agents").hide("scale");
+    }
+  });
+
   setup_modals();
+
+  $("#logout").click(async function(event){
+    event.preventDefault();
+    await fetch("/api/logout", { method: "POST" });
+    window.location = "/login";
+  });
 }
 
 $(main);
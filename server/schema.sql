@@ -7,7 +7,31 @@ CREATE TABLE IF NOT EXISTS agents (
     free_cpus   INTEGER DEFAULT 0,
     free_ram    INTEGER DEFAULT 0,
     cpus        INTEGER DEFAULT 0,
-    ram         INTEGER DEFAULT 0
+    ram         INTEGER DEFAULT 0,
+    last_seq    INTEGER NOT NULL DEFAULT 0,
+    secret_hash TEXT,
+    status_changed TEXT,
+    last_seen   TEXT,
+    created_by  TEXT NOT NULL DEFAULT "",
+    version     TEXT,
+    state       TEXT NOT NULL DEFAULT "active",
+    load_avg    REAL,
+    available_ram INTEGER,
+    disk_total  INTEGER,
+    disk_free   INTEGER,
+    drift       TEXT,
+    reconciled_at TEXT,
+    reserved_cpus INTEGER NOT NULL DEFAULT 0,
+    reserved_ram INTEGER NOT NULL DEFAULT 0,
+    cpu_overcommit REAL NOT NULL DEFAULT 1.0,
+    ram_overcommit REAL NOT NULL DEFAULT 1.0
+);
+
+CREATE TABLE IF NOT EXISTS agent_labels (
+    agent_guid  TEXT NOT NULL,
+    key         TEXT NOT NULL,
+    value       TEXT NOT NULL,
+    PRIMARY KEY (agent_guid, key)
 );
 
 CREATE TABLE IF NOT EXISTS job_collection (
@@ -23,7 +47,15 @@ CREATE TABLE IF NOT EXISTS job_collection (
     target      TEXT NOT NULL,
     corpus      TEXT NOT NULL,
     status      TEXT NOT NULL,
-    crash_auto_analyze BOOLEAN NOT NULL CHECK (crash_auto_analyze IN (0, 1))
+    crash_auto_analyze BOOLEAN NOT NULL CHECK (crash_auto_analyze IN (0, 1)),
+    created_by  TEXT NOT NULL DEFAULT "",
+    priority    INTEGER NOT NULL DEFAULT 0,
+    strategy    TEXT NOT NULL DEFAULT "binpack",
+    min_cores   INTEGER NOT NULL DEFAULT 1,
+    pinned_agents TEXT NOT NULL DEFAULT "",
+    constraints TEXT NOT NULL DEFAULT "",
+    env         TEXT NOT NULL DEFAULT "{}",
+    fuzzer      TEXT NOT NULL DEFAULT "{}"
 );
 
 CREATE TABLE IF NOT EXISTS jobs (
@@ -46,5 +78,63 @@ CREATE TABLE IF NOT EXISTS crashes (
     analyzed    TEXT,
     hash        TEXT NOT NULL,
     creation_date TEXT NOT NULL,
-    size        INTEGER NOT NULL CHECK (size > 0)
+    size        INTEGER NOT NULL CHECK (size > 0),
+    signature   TEXT,
+    bucket_id   INTEGER REFERENCES buckets(id),
+    minimized   TEXT,
+    minimize_status TEXT,
+    minimize_msg TEXT
+);
+
+CREATE TABLE IF NOT EXISTS buckets (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature   TEXT NOT NULL UNIQUE,
+    crash_type  TEXT NOT NULL,
+    first_seen  TEXT NOT NULL,
+    last_seen   TEXT NOT NULL,
+    hits        INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS corpora (
+    guid        TEXT PRIMARY KEY NOT NULL,
+    name        TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL,
+    creation_date TEXT NOT NULL,
+    created_by  TEXT NOT NULL DEFAULT "",
+    source_job  TEXT,
+    size        INTEGER NOT NULL DEFAULT 0,
+    status      TEXT NOT NULL CHECK (status IN ('minimizing', 'ready', 'error')),
+    last_msg    TEXT NOT NULL DEFAULT ""
+);
+
+CREATE TABLE IF NOT EXISTS artifacts (
+    hash        TEXT PRIMARY KEY NOT NULL,
+    size        INTEGER NOT NULL DEFAULT 0,
+    creation_date TEXT NOT NULL,
+    created_by  TEXT NOT NULL DEFAULT "",
+    last_used   TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS job_artifacts (
+    collection_guid TEXT NOT NULL,
+    file        TEXT NOT NULL,
+    hash        TEXT NOT NULL REFERENCES artifacts(hash),
+    PRIMARY KEY (collection_guid, file)
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    guid        TEXT PRIMARY KEY NOT NULL,
+    username    TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role        TEXT NOT NULL CHECK (role IN ('viewer', 'operator', 'admin')),
+    creation_date TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tokens (
+    hash        TEXT PRIMARY KEY NOT NULL,
+    user_guid   TEXT NOT NULL,
+    kind        TEXT NOT NULL CHECK (kind IN ('session', 'api')),
+    description TEXT NOT NULL DEFAULT "",
+    creation_date TEXT NOT NULL,
+    expires     INTEGER
 );
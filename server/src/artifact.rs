@@ -0,0 +1,101 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use actix_multipart::Field;
+use actix_web::Error;
+use futures::StreamExt;
+use sha3::{Digest, Sha3_256};
+use uuid::Uuid;
+
+use crate::config::CONFIG;
+use crate::utils::link_or_copy;
+
+fn artifacts_dir() -> PathBuf {
+    Path::new(&CONFIG.nfs_dir).join("artifacts")
+}
+
+pub fn artifact_path(hash: &str) -> PathBuf {
+    artifacts_dir().join(hash)
+}
+
+pub fn upload_too_large() -> Error {
+    actix_web::error::ErrorPayloadTooLarge(format!(
+        "the upload exceeds {} MB",
+        CONFIG.max_upload_mb
+    ))
+}
+
+/// Streams the uploaded file into the store while hashing it. Returns the
+/// hash and the size of the content, the same content is stored only once
+pub async fn store_upload(mut field: Field) -> Result<(String, u64), Error> {
+    let dir = artifacts_dir();
+    fs::create_dir_all(&dir)?;
+
+    // Written aside first as the name isn't known before the upload ends
+    let part = dir.join(format!("{}.part", Uuid::new_v4()));
+    let mut file = fs::File::create(&part)?;
+    let mut hasher = Sha3_256::new();
+    let mut size = 0;
+    while let Some(chunk) = field.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                _ = fs::remove_file(&part);
+                return Err(err.into());
+            }
+        };
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+        if size > CONFIG.max_upload_size() {
+            _ = fs::remove_file(&part);
+            return Err(upload_too_large());
+        }
+        if let Err(err) = file.write_all(&chunk) {
+            _ = fs::remove_file(&part);
+            return Err(err.into());
+        }
+    }
+    drop(file);
+
+    let hash = format!("{:x}", hasher.finalize());
+    let path = artifact_path(&hash);
+    if path.exists() {
+        fs::remove_file(&part)?;
+    } else {
+        fs::rename(&part, &path)?;
+    }
+
+    Ok((hash, size))
+}
+
+/// Puts a file of a new job into the store, for the corpora which don't come
+/// from an artifact. Returns the hash and the size of the file
+pub fn store_file(path: &Path) -> io::Result<(String, u64)> {
+    let mut hasher = Sha3_256::new();
+    let size = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    let hash = format!("{:x}", hasher.finalize());
+
+    let dst = artifact_path(&hash);
+    if !dst.exists() {
+        fs::create_dir_all(artifacts_dir())?;
+        link_or_copy(path, &dst)?;
+    }
+
+    Ok((hash, size))
+}
+
+/// Puts the stored artifact into the job directory
+pub fn link_artifact(hash: &str, dst: &Path) -> io::Result<()> {
+    link_or_copy(&artifact_path(hash), dst)
+}
+
+/// The jobs keep their own links to the removed artifact
+pub fn remove_artifact(hash: &str) -> io::Result<()> {
+    match fs::remove_file(artifact_path(hash)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,204 @@
+use std::rc::Rc;
+
+use actix_http::{header, Method, Payload};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::error;
+use sqlx::SqlitePool;
+
+use crate::config::CONFIG;
+use crate::models::{Role, User, UserCreateRequest};
+
+pub const SESSION_COOKIE: &str = "yafi_session";
+
+/// Returns the role required to access the route, None for public routes
+fn required_role(method: &Method, path: &str) -> Option<Role> {
+    if path == "/login" || path == "/api/login" || path.starts_with("/static/") {
+        return None;
+    }
+
+    // Agents authenticate with the join token on their own
+    if path == "/api/agent/register"
+        || (path.starts_with("/api/agent/") && path.ends_with("/heartbeat"))
+    {
+        return None;
+    }
+
+    if path.starts_with("/api/user") {
+        return Some(Role::Admin);
+    }
+
+    if path.starts_with("/api/agent") && method != Method::GET {
+        return Some(Role::Admin);
+    }
+
+    if method == Method::GET
+        || path.starts_with("/api/stats")
+        || path == "/api/token"
+        || path == "/api/logout"
+    {
+        return Some(Role::Viewer);
+    }
+
+    Some(Role::Operator)
+}
+
+/// The path as the router matches it. req.path() is still percent-encoded,
+/// so /api/%75ser would route to /api/user while skipping its checks
+fn route_path(req: &ServiceRequest) -> &str {
+    req.match_info().as_str()
+}
+
+pub fn request_token(req: &HttpRequest) -> Option<String> {
+    if let Some(value) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(value.to_string());
+    }
+
+    req.cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+}
+
+async fn authenticate(req: &ServiceRequest) -> Option<User> {
+    let pool = req.app_data::<web::Data<SqlitePool>>()?;
+    let token = request_token(req.request())?;
+    match User::get_by_token(&token, pool.get_ref()).await {
+        Ok(user) => user,
+        Err(err) => {
+            error!("Failed to authenticate request: {}", err);
+            None
+        }
+    }
+}
+
+/// Creates the admin user on the first start
+pub async fn bootstrap_admin(pool: &SqlitePool) {
+    let password = match &CONFIG.admin_password {
+        Some(password) => password,
+        None => return,
+    };
+
+    match User::count(pool).await {
+        Ok(0) => {
+            let req = UserCreateRequest {
+                username: "admin".to_string(),
+                password: password.clone(),
+                role: Role::Admin,
+            };
+            if let Err(err) = User::create(&req, pool).await {
+                error!("Failed to create admin user: {}", err);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => error!("Failed to count users: {}", err),
+    }
+}
+
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let required = match required_role(req.method(), route_path(&req)) {
+                Some(role) => role,
+                None => return Ok(service.call(req).await?.map_into_left_body()),
+            };
+
+            let response = match authenticate(&req).await {
+                Some(user) if user.role >= required => {
+                    req.extensions_mut().insert(user);
+                    return Ok(service.call(req).await?.map_into_left_body());
+                }
+                Some(_) => HttpResponse::Forbidden().body("Insufficient permissions"),
+                None if route_path(&req).starts_with("/api/") => {
+                    HttpResponse::Unauthorized().body("Authentication required")
+                }
+                None => HttpResponse::Found()
+                    .insert_header((header::LOCATION, "/login"))
+                    .finish(),
+            };
+
+            Ok(req.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+impl FromRequest for User {
+    type Error = Error;
+    type Future = Ready<Result<User, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<User>()
+                .cloned()
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Authentication required")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn it_requires_roles_on_the_decoded_path() {
+        let req = TestRequest::post().uri("/api/%75ser").to_srv_request();
+        assert_eq!(route_path(&req), "/api/user");
+        assert_eq!(
+            required_role(req.method(), route_path(&req)),
+            Some(Role::Admin)
+        );
+
+        let req = TestRequest::delete()
+            .uri("/api/%61gent/0e5b0a4c")
+            .to_srv_request();
+        assert_eq!(
+            required_role(req.method(), route_path(&req)),
+            Some(Role::Admin)
+        );
+    }
+}
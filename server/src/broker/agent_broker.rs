@@ -1,45 +1,105 @@
+use std::time::Duration;
+
 use futures::StreamExt;
-use log::error;
+use log::{error, info, warn};
 use sqlx::SqlitePool;
-use tokio::sync::mpsc::Receiver;
-use tonic::transport::Channel;
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    oneshot,
+};
+use tokio::{task, time};
 
 use crate::protos::agent::job_client::JobClient;
 use crate::protos::agent::system_info_client::SystemInfoClient;
 use crate::protos::agent::updates_client::UpdatesClient;
-use crate::protos::agent::{update::UpdateKind, Empty, JobCreateRequest, SysInfo};
-use crate::protos::agent::{CrashMsg, JobGuid};
+use crate::protos::agent::{
+    update::UpdateKind, Empty, JobCreateRequest, JobInfoContainerList, SysInfo, Update,
+};
+use crate::protos::agent::{
+    AnalyzeRequest, CorpusMinimizeRequest, CorpusMsg, CrashMsg, JobGuid, LogsRequest, MinimizeMsg,
+    MinimizeRequest, UpdatesAck, UpdatesRequest,
+};
+
+use super::agent_connection::{self, AgentChannel};
 
+use crate::corpus::store_minimized;
 use crate::models::Agent;
+use crate::models::Corpus;
 use crate::models::Crash;
 use crate::models::Job;
 
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(300);
+
 #[derive(Debug)]
 pub enum Request {
-    JobCreate { job: JobCreateRequest },
-    JobStop { guid: String },
+    JobCreate {
+        job: JobCreateRequest,
+    },
+    JobStop {
+        guid: String,
+    },
+    JobDestroy {
+        guid: String,
+    },
+    JobPause {
+        guid: String,
+    },
+    JobResume {
+        guid: String,
+    },
+    JobLogs {
+        guid: String,
+        idx: u64,
+        sender: Sender<Result<String, String>>,
+    },
+    CrashAnalyze {
+        request: AnalyzeRequest,
+        sender: oneshot::Sender<Result<String, String>>,
+    },
+    CrashMinimize {
+        request: MinimizeRequest,
+    },
+    CorpusMinimize {
+        request: CorpusMinimizeRequest,
+    },
 }
 
 #[derive(Debug)]
 pub struct AgentBroker {
     guid: String,
     db_pool: SqlitePool,
-    job_client: Option<JobClient<Channel>>,
-    updates_client: Option<UpdatesClient<Channel>>,
-    sys_info_client: Option<SystemInfoClient<Channel>>,
+    job_client: Option<JobClient<AgentChannel>>,
+    updates_client: Option<UpdatesClient<AgentChannel>>,
+    sys_info_client: Option<SystemInfoClient<AgentChannel>>,
+    scheduler: Sender<()>,
+    connected: bool,
 }
 
 impl AgentBroker {
-    pub fn new(guid: String, db_pool: SqlitePool) -> AgentBroker {
+    pub fn new(guid: String, db_pool: SqlitePool, scheduler: Sender<()>) -> AgentBroker {
         AgentBroker {
             guid,
             db_pool,
             job_client: None,
             updates_client: None,
             sys_info_client: None,
+            scheduler,
+            connected: false,
         }
     }
 
+    /// Lets the job scheduler place the queued jobs on the freed resources
+    fn wake_scheduler(&self) {
+        // A pending wakeup already covers this one
+        _ = self.scheduler.try_send(());
+    }
+
+    pub fn has_connected(&self) -> bool {
+        self.connected
+    }
+
     async fn get_sysinfo(&mut self) -> Option<SysInfo> {
         if let Some(sys_info_client) = &mut self.sys_info_client {
             let request = tonic::Request::new(Empty {});
@@ -54,46 +114,26 @@ impl AgentBroker {
 
     async fn init(&mut self) -> Result<(), String> {
         if let Ok(Some(agent)) = Agent::get_by_guid(&self.guid, &self.db_pool).await {
-            if let Ok(conn) = JobClient::connect(agent.endpoint.clone()).await {
-                self.job_client = Some(conn);
-            } else {
-                if agent.status == "up" {
-                    self.update_status("down").await;
-                }
-                return Err(format!(
-                    "agent.JobClient {} couldn't establish connection",
-                    self.guid
-                ));
-            }
-
-            if let Ok(conn) = UpdatesClient::connect(agent.endpoint.clone()).await {
-                self.updates_client = Some(conn);
-            } else {
-                if agent.status != "up" {
-                    self.update_status("down").await;
+            let channel = match agent_connection::connect(&agent.endpoint).await {
+                Ok(channel) => channel,
+                Err(err) => {
+                    if agent.status != "init" {
+                        if let Err(status_err) = self.update_status("down").await {
+                            error!("{:?}", status_err);
+                        }
+                    }
+                    return Err(format!("agent {}: {}", self.guid, err));
                 }
-                return Err(format!(
-                    "agent.UpdatesClient {} couldn't establish connection",
-                    self.guid
-                ));
-            }
+            };
 
-            if let Ok(conn) = SystemInfoClient::connect(agent.endpoint).await {
-                self.sys_info_client = Some(conn);
-            } else {
-                if agent.status != "up" {
-                    self.update_status("down").await;
-                }
-                return Err(format!(
-                    "agent.SystemInfoClient {} couldn't establish connection",
-                    self.guid
-                ));
-            }
+            self.job_client = Some(JobClient::new(channel.clone()));
+            self.updates_client = Some(UpdatesClient::new(channel.clone()));
+            self.sys_info_client = Some(SystemInfoClient::new(channel));
 
             if agent.status == "init" {
                 if let Some(sys_info) = self.get_sysinfo().await {
                     match Agent::update_sys_info(&self.guid, &sys_info, &self.db_pool).await {
-                        Ok(_) => self.update_status("down").await,
+                        Ok(_) => self.update_status("down").await?,
                         Err(err) => return Err(format!("Failed to update sys info: {err}")),
                     }
                 }
@@ -105,27 +145,59 @@ impl AgentBroker {
         }
     }
 
-    async fn sync_jobs(&mut self) -> Result<(), String> {
+    async fn get_all_jobs(&mut self) -> Result<JobInfoContainerList, String> {
         if let Some(job_client) = &mut self.job_client {
             let request = tonic::Request::new(Empty {});
             match job_client.get_all(request).await {
-                Ok(response) => {
-                    match Job::sync_jobs(&self.guid, response.into_inner(), &self.db_pool).await {
-                        Ok(_) => {}
-                        Err(err) => {
-                            return Err(format!(
-                                "failed to sync jobs with {}: {:?}",
-                                self.guid, err
-                            ))
-                        }
-                    }
-                }
-                Err(err) => {
-                    return Err(format!("failed to sync jobs with {}: {:?}", self.guid, err))
-                }
+                Ok(response) => Ok(response.into_inner()),
+                Err(err) => Err(format!("failed to get jobs of {}: {:?}", self.guid, err)),
             }
         } else {
-            return Err(format!("failed to get job_client for {}", self.guid));
+            Err(format!("failed to get job_client for {}", self.guid))
+        }
+    }
+
+    async fn sync_jobs(&mut self) -> Result<(), String> {
+        let jobs = self.get_all_jobs().await?;
+        match Job::sync_jobs(&self.guid, jobs, &self.db_pool).await {
+            Ok(_) => {}
+            Err(err) => return Err(format!("failed to sync jobs with {}: {:?}", self.guid, err)),
+        }
+
+        Ok(())
+    }
+
+    /// Catches up with the sub-jobs the agent actually runs and recomputes
+    /// its free resources from them and from the host metrics
+    async fn reconcile(&mut self) -> Result<(), String> {
+        let sys_info = match self.get_sysinfo().await {
+            Some(sys_info) => sys_info,
+            None => return Err(format!("failed to get sys info of {}", self.guid)),
+        };
+        let jobs = self.get_all_jobs().await?;
+        let running: Vec<JobCreateRequest> = jobs
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.status.as_str(), "init" | "alive" | "paused"))
+            .cloned()
+            .collect();
+
+        if let Err(err) = Job::sync_jobs(&self.guid, jobs, &self.db_pool).await {
+            return Err(format!("failed to sync jobs with {}: {:?}", self.guid, err));
+        }
+
+        match Agent::reconcile(&self.guid, &sys_info, &running, &self.db_pool).await {
+            Ok(Some(drift)) => {
+                warn!("Resources of agent {} have drifted: {}", self.guid, drift);
+                self.wake_scheduler();
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return Err(format!(
+                    "failed to reconcile resources of {}: {:?}",
+                    self.guid, err
+                ))
+            }
         }
 
         Ok(())
@@ -145,51 +217,59 @@ impl AgentBroker {
         Ok(())
     }
 
-    async fn set_job_status(&self, job_guid: &str, status: &str) {
+    async fn set_job_status(&self, job_guid: &str, status: &str) -> Result<(), String> {
         match Job::set_job_status(&self.guid, job_guid, status, &self.db_pool).await {
-            Ok(_) => {}
-            Err(err) => {
-                error!(
-                    "Failed to set {} job status for {}: {:?}",
-                    job_guid, self.guid, err
-                );
+            Ok(_) => {
+                if status == "paused" {
+                    self.wake_scheduler();
+                }
+                Ok(())
             }
+            Err(err) => Err(format!(
+                "failed to set {} job status for {}: {:?}",
+                job_guid, self.guid, err
+            )),
         }
     }
 
-    async fn set_job_last_msg(&self, job_guid: &str, last_msg: &str) {
-        match Job::set_job_last_msg(&self.guid, job_guid, last_msg, &self.db_pool).await {
-            Ok(_) => {}
-            Err(err) => {
-                error!(
-                    "Failed to set {} job last_msg for {}: {:?}",
+    async fn set_job_last_msg(&self, job_guid: &str, last_msg: &str) -> Result<(), String> {
+        Job::set_job_last_msg(&self.guid, job_guid, last_msg, &self.db_pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "failed to set {} job last_msg for {}: {:?}",
                     job_guid, self.guid, err
-                );
-            }
-        }
+                )
+            })
     }
 
-    async fn set_job_log(&self, job_guid: &str, log: &str) {
-        match Job::set_job_log(&self.guid, job_guid, log, &self.db_pool).await {
-            Ok(_) => {}
-            Err(err) => {
-                error!(
-                    "Failed to set {} job log for {}: {:?}",
+    async fn set_job_log(&self, job_guid: &str, log: &str) -> Result<(), String> {
+        Job::set_job_log(&self.guid, job_guid, log, &self.db_pool)
+            .await
+            .map_err(|err| {
+                format!(
+                    "failed to set {} job log for {}: {:?}",
                     job_guid, self.guid, err
-                );
-            }
-        }
+                )
+            })
     }
 
-    async fn complete_job(&self, job_guid: &str, last_msg: &str, status: &str) {
+    async fn complete_job(
+        &self,
+        job_guid: &str,
+        last_msg: &str,
+        status: &str,
+    ) -> Result<(), String> {
         match Job::complete_job(&self.guid, job_guid, last_msg, status, &self.db_pool).await {
-            Ok(_) => {}
-            Err(err) => {
-                error!(
-                    "Failed to complete {} job for {}: {:?}",
-                    job_guid, self.guid, err
-                );
+            Ok(_) => {
+                self.wake_scheduler();
+                self.finish_draining().await;
+                Ok(())
             }
+            Err(err) => Err(format!(
+                "failed to complete {} job for {}: {:?}",
+                job_guid, self.guid, err
+            )),
         }
     }
 
@@ -209,31 +289,436 @@ impl AgentBroker {
         Ok(())
     }
 
-    async fn update_status(&self, status: &str) {
+    async fn destroy_job(&mut self, job_guid: &str) -> Result<(), String> {
+        if let Some(job_client) = &mut self.job_client {
+            let request = tonic::Request::new(JobGuid {
+                guid: job_guid.to_string(),
+            });
+            match job_client.destroy(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to destroy job: {:?}", err)),
+            }
+        } else {
+            return Err(format!("failed to get job_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
+    async fn pause_job(&mut self, job_guid: &str) -> Result<(), String> {
+        if let Some(job_client) = &mut self.job_client {
+            let request = tonic::Request::new(JobGuid {
+                guid: job_guid.to_string(),
+            });
+            match job_client.pause(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to pause job: {:?}", err)),
+            }
+        } else {
+            return Err(format!("failed to get job_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
+    async fn resume_job(&mut self, job_guid: &str) -> Result<(), String> {
+        if let Some(job_client) = &mut self.job_client {
+            let request = tonic::Request::new(JobGuid {
+                guid: job_guid.to_string(),
+            });
+            match job_client.resume(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to resume job: {:?}", err)),
+            }
+        } else {
+            return Err(format!("failed to get job_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
+    async fn stream_job_logs(
+        &mut self,
+        job_guid: &str,
+        idx: u64,
+        sender: Sender<Result<String, String>>,
+    ) -> Result<(), String> {
+        let result = match &mut self.job_client {
+            Some(job_client) => {
+                let request = tonic::Request::new(LogsRequest {
+                    guid: job_guid.to_string(),
+                    idx,
+                });
+                job_client
+                    .logs(request)
+                    .await
+                    .map(|response| response.into_inner())
+                    .map_err(|err| format!("failed to stream job logs: {}", err.message()))
+            }
+            None => Err(format!("failed to get job_client for {:?}", self.guid)),
+        };
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => {
+                _ = sender.send(Err(err.clone())).await;
+                return Err(err);
+            }
+        };
+
+        task::spawn(async move {
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk
+                    .map(|chunk| chunk.data)
+                    .map_err(|err| format!("log stream failed: {}", err.message()));
+                let failed = chunk.is_err();
+                if sender.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn analyze_crash(
+        &mut self,
+        request: AnalyzeRequest,
+        sender: oneshot::Sender<Result<String, String>>,
+    ) -> Result<(), String> {
+        let mut job_client = match &self.job_client {
+            Some(job_client) => job_client.clone(),
+            None => {
+                let err = format!("failed to get job_client for {:?}", self.guid);
+                _ = sender.send(Err(err.clone()));
+                return Err(err);
+            }
+        };
+
+        // The analysis may take minutes, don't hold up the other requests
+        task::spawn(async move {
+            let result = match job_client.analyze_crash(request).await {
+                Ok(response) => Ok(response.into_inner().result),
+                Err(err) => Err(format!("failed to analyze crash: {}", err.message())),
+            };
+            _ = sender.send(result);
+        });
+
+        Ok(())
+    }
+
+    async fn minimize_crash(&mut self, request: MinimizeRequest) -> Result<(), String> {
+        if let Some(job_client) = &mut self.job_client {
+            let request = tonic::Request::new(request);
+            match job_client.minimize_crash(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to minimize crash: {}", err.message())),
+            }
+        } else {
+            return Err(format!("failed to get job_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
+    async fn set_minimize_status(&self, crash_guid: &str, status: &str, last_msg: &str) {
+        let msg = MinimizeMsg {
+            crash_guid: crash_guid.to_string(),
+            status: status.to_string(),
+            last_msg: last_msg.to_string(),
+            minimized: None,
+        };
+        if let Err(err) = self.minimize_update(&msg).await {
+            error!("{:?}", err);
+        }
+    }
+
+    async fn minimize_update(&self, msg: &MinimizeMsg) -> Result<(), String> {
+        Crash::set_minimize_status(
+            &msg.crash_guid,
+            &msg.status,
+            &msg.last_msg,
+            msg.minimized.as_deref(),
+            &self.db_pool,
+        )
+        .await
+        .map_err(|err| {
+            format!(
+                "failed to update minimization of {}: {}",
+                msg.crash_guid, err
+            )
+        })
+    }
+
+    async fn minimize_corpus(&mut self, request: CorpusMinimizeRequest) -> Result<(), String> {
+        if let Some(job_client) = &mut self.job_client {
+            let request = tonic::Request::new(request);
+            match job_client.minimize_corpus(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to minimize corpus: {}", err.message())),
+            }
+        } else {
+            return Err(format!("failed to get job_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
+    async fn set_corpus_status(&self, corpus_guid: &str, status: &str, last_msg: &str) {
+        if let Err(err) =
+            Corpus::set_status(corpus_guid, status, last_msg, None, &self.db_pool).await
+        {
+            error!("Failed to update corpus {}: {}", corpus_guid, err);
+        }
+    }
+
+    /// The minimized corpus is left in the job directory, it gets moved into
+    /// the corpora once it is ready
+    async fn corpus_update(&self, msg: &CorpusMsg) -> Result<(), String> {
+        if msg.status != "ready" {
+            return Corpus::set_status(
+                &msg.corpus_guid,
+                &msg.status,
+                &msg.last_msg,
+                None,
+                &self.db_pool,
+            )
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("failed to update corpus {}: {}", msg.corpus_guid, err));
+        }
+
+        let source_job = match Corpus::find(&msg.corpus_guid, &self.db_pool).await {
+            Ok(Some(corpus)) => corpus.source_job.unwrap_or_default(),
+            Ok(None) => return Err(format!("corpus {} doesn't exist", msg.corpus_guid)),
+            Err(err) => {
+                return Err(format!(
+                    "failed to fetch corpus {}: {}",
+                    msg.corpus_guid, err
+                ))
+            }
+        };
+
+        let size = match store_minimized(&source_job, &msg.corpus_guid) {
+            Ok(size) => i64::try_from(size).ok(),
+            Err(err) => {
+                self.set_corpus_status(
+                    &msg.corpus_guid,
+                    "error",
+                    &format!("Failed to store the minimized corpus: {}", err),
+                )
+                .await;
+                return Ok(());
+            }
+        };
+
+        Corpus::set_status(
+            &msg.corpus_guid,
+            "ready",
+            &msg.last_msg,
+            size,
+            &self.db_pool,
+        )
+        .await
+        .map(|_| ())
+        .map_err(|err| format!("failed to update corpus {}: {}", msg.corpus_guid, err))
+    }
+
+    async fn health_check(&mut self) -> Result<(), String> {
+        match time::timeout(HEALTH_CHECK_TIMEOUT, self.get_sysinfo()).await {
+            Ok(Some(_)) => {
+                if let Err(err) = Agent::touch(&self.guid, &self.db_pool).await {
+                    error!("Failed to update last_seen for {}: {:?}", self.guid, err);
+                }
+                Ok(())
+            }
+            Ok(None) => Err(format!("agent {} failed health check", self.guid)),
+            Err(_) => Err(format!("agent {} health check timed out", self.guid)),
+        }
+    }
+
+    /// Handles a request while the agent is not connected
+    pub async fn reject(&self, request: Request) {
+        match request {
+            Request::JobCreate { job } => {
+                if let Err(err) = self
+                    .complete_job(&job.job_guid, "Agent is not connected", "error")
+                    .await
+                {
+                    error!("{:?}", err);
+                }
+            }
+            Request::JobStop { guid } => {
+                error!(
+                    "Failed to stop job {}: agent {} is not connected",
+                    guid, self.guid
+                );
+            }
+            Request::JobDestroy { guid } => {
+                error!(
+                    "Failed to destroy job {}: agent {} is not connected",
+                    guid, self.guid
+                );
+            }
+            Request::JobPause { guid } => {
+                error!(
+                    "Failed to pause job {}: agent {} is not connected",
+                    guid, self.guid
+                );
+            }
+            Request::JobResume { guid } => {
+                // Give back the resources reserved for the resumed sub-job
+                if let Err(err) = self.set_job_status(&guid, "paused").await {
+                    error!("{:?}", err);
+                }
+            }
+            Request::JobLogs { sender, .. } => {
+                _ = sender
+                    .send(Err(format!("agent {} is not connected", self.guid)))
+                    .await;
+            }
+            Request::CrashAnalyze { sender, .. } => {
+                _ = sender.send(Err(format!("agent {} is not connected", self.guid)));
+            }
+            Request::CrashMinimize { request } => {
+                self.set_minimize_status(&request.crash_guid, "error", "Agent is not connected")
+                    .await;
+            }
+            Request::CorpusMinimize { request } => {
+                self.set_corpus_status(&request.corpus_guid, "error", "Agent is not connected")
+                    .await;
+            }
+        }
+    }
+
+    async fn finish_draining(&self) {
+        match Agent::finish_draining(&self.guid, &self.db_pool).await {
+            Ok(true) => info!("Agent {} has been drained", self.guid),
+            Ok(false) => {}
+            Err(err) => error!("Failed to finish draining {}: {:?}", self.guid, err),
+        }
+    }
+
+    async fn update_status(&self, status: &str) -> Result<(), String> {
         Agent::update_status(&self.guid, status, &self.db_pool)
             .await
-            .unwrap();
+            .map_err(|err| {
+                format!(
+                    "failed to set status of {} to {}: {:?}",
+                    self.guid, status, err
+                )
+            })
     }
 
-    async fn new_crash(&self, crash_msg: &CrashMsg) {
-        match Crash::new_crash(crash_msg, &self.db_pool).await {
-            Ok(()) => {}
+    async fn new_crash(&self, crash_msg: &CrashMsg) -> Result<(), String> {
+        Crash::new_crash(crash_msg, &self.db_pool)
+            .await
+            .map_err(|err| format!("failed to process new crash: {}", err))
+    }
+
+    /// Applies an update of the agent. An update which fails is logged and
+    /// skipped, it would be replayed from the outbox over and over otherwise.
+    /// Only the transport errors of the stream make the broker reconnect
+    async fn process_update(&self, update: Update) {
+        let kind = match update.update_kind {
+            Some(kind) => kind,
+            None => return,
+        };
+
+        let result = match &kind {
+            UpdateKind::JobMsg(job_update) => {
+                let mut result = match &job_update.status {
+                    Some(status) => {
+                        let last_msg = job_update.last_msg.as_deref().unwrap_or_default();
+                        if status == "completed" || status == "error" || status == "timeout" {
+                            self.complete_job(&job_update.guid, last_msg, status).await
+                        } else {
+                            self.set_job_status(&job_update.guid, status).await
+                        }
+                    }
+                    None => match &job_update.last_msg {
+                        Some(last_msg) => self.set_job_last_msg(&job_update.guid, last_msg).await,
+                        None => Ok(()),
+                    },
+                };
+
+                if let Some(log) = &job_update.log {
+                    result = result.and(self.set_job_log(&job_update.guid, log).await);
+                }
+                result
+            }
+            UpdateKind::CrashMsg(crash_msg) => self.new_crash(crash_msg).await,
+            UpdateKind::MinimizeMsg(minimize_msg) => self.minimize_update(minimize_msg).await,
+            UpdateKind::CorpusMsg(corpus_msg) => self.corpus_update(corpus_msg).await,
+        };
+
+        if let Err(err) = result {
+            // The job log is left out, it can be large
+            let target = match &kind {
+                UpdateKind::JobMsg(msg) => format!("job {} status {:?}", msg.guid, msg.status),
+                UpdateKind::CrashMsg(msg) => format!("crash {} of job {}", msg.name, msg.job_guid),
+                UpdateKind::MinimizeMsg(msg) => format!("minimization of crash {}", msg.crash_guid),
+                UpdateKind::CorpusMsg(msg) => format!("corpus {}", msg.corpus_guid),
+            };
+            error!(
+                "Skipping update {} of agent {} for {}: {}",
+                update.seq, self.guid, target, err
+            );
+        }
+    }
+
+    async fn set_last_seq(&self, seq: u64) {
+        match Agent::set_last_seq(&self.guid, seq, &self.db_pool).await {
+            Ok(_) => {}
             Err(err) => {
-                error!("Failed to process new crash {}", err);
+                error!("Failed to set last update seq for {}: {:?}", self.guid, err);
             }
         }
     }
 
+    async fn ack_updates(&mut self, seq: u64) -> Result<(), String> {
+        if let Some(updates_client) = &mut self.updates_client {
+            let request = tonic::Request::new(UpdatesAck { seq });
+            match updates_client.ack(request).await {
+                Ok(_) => {}
+                Err(err) => return Err(format!("failed to ack updates: {:?}", err)),
+            }
+        } else {
+            return Err(format!("failed to get updates_client for {:?}", self.guid));
+        }
+
+        Ok(())
+    }
+
     pub async fn main(&mut self, broker_messages: &mut Receiver<Request>) -> Result<(), String> {
         self.init().await?;
         self.sync_jobs().await?;
 
+        let mut last_seq = match Agent::get_last_seq(&self.guid, &self.db_pool).await {
+            Ok(seq) => seq,
+            Err(err) => return Err(format!("failed to get last update seq: {:?}", err)),
+        };
+        let mut acked_seq = last_seq;
+
         let mut stream = match &mut self.updates_client {
-            Some(updates_client) => updates_client.get(Empty {}).await.unwrap().into_inner(),
+            Some(updates_client) => match updates_client.get(UpdatesRequest { last_seq }).await {
+                Ok(response) => response.into_inner(),
+                Err(err) => {
+                    return Err(format!(
+                        "agent.UpdatesClient {} failed to subscribe: {:?}",
+                        self.guid, err
+                    ))
+                }
+            },
             _ => return Err(format!("agent.UpdatesClient {} is not ready", self.guid)),
         };
 
-        self.update_status("up").await;
+        self.update_status("up").await?;
+        self.connected = true;
+        self.wake_scheduler();
+
+        let mut ack_interval = time::interval(Duration::from_secs(10));
+        let mut health_interval = time::interval(HEALTH_CHECK_INTERVAL);
+        let mut reconcile_interval = time::interval(RECONCILE_INTERVAL);
 
         loop {
             tokio::select! {
@@ -245,7 +730,9 @@ impl AgentBroker {
                                 match self.create_job(job).await {
                                     Ok(_) => {},
                                     Err(err) => {
-                                        self.complete_job(&job_guid, &err.to_string(), "error").await;
+                                        if let Err(err) = self.complete_job(&job_guid, &err.to_string(), "error").await {
+                                            error!("{:?}", err);
+                                        }
                                     }
                                 }
                             },
@@ -257,6 +744,67 @@ impl AgentBroker {
                                     }
                                 }
                             },
+                            Request::JobDestroy { guid } => {
+                                match self.destroy_job(&guid).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                    }
+                                }
+                            },
+                            Request::JobPause { guid } => {
+                                match self.pause_job(&guid).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                    }
+                                }
+                            },
+                            Request::JobResume { guid } => {
+                                match self.resume_job(&guid).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                        if let Err(err) = self.set_job_status(&guid, "paused").await {
+                                            error!("{:?}", err);
+                                        }
+                                    }
+                                }
+                            },
+                            Request::JobLogs { guid, idx, sender } => {
+                                match self.stream_job_logs(&guid, idx, sender).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                    }
+                                }
+                            },
+                            Request::CrashAnalyze { request, sender } => {
+                                match self.analyze_crash(request, sender).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                    }
+                                }
+                            },
+                            Request::CrashMinimize { request } => {
+                                let crash_guid = request.crash_guid.clone();
+                                match self.minimize_crash(request).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        self.set_minimize_status(&crash_guid, "error", &err).await;
+                                    }
+                                }
+                            },
+                            Request::CorpusMinimize { request } => {
+                                let corpus_guid = request.corpus_guid.clone();
+                                match self.minimize_corpus(request).await {
+                                    Ok(_) => {},
+                                    Err(err) => {
+                                        self.set_corpus_status(&corpus_guid, "error", &err).await;
+                                    }
+                                }
+                            },
                         },
                         None => break
                     }
@@ -265,32 +813,16 @@ impl AgentBroker {
                     Some(update) => {
                         match update {
                             Ok(update) => {
-                                if let Some(kind) = update.update_kind {
-                                    match kind {
-                                        UpdateKind::JobMsg(job_update) => {
-                                            if let Some(status) = job_update.status {
-                                                let last_msg = job_update.last_msg.unwrap_or_default();
-                                                if status == "completed" || status == "error" {
-                                                    self.complete_job(&job_update.guid, &last_msg, &status).await;
-                                                } else {
-                                                    self.set_job_status(&job_update.guid, &status).await;
-                                                }
-                                            } else if let Some(last_msg) = job_update.last_msg {
-                                                self.set_job_last_msg(&job_update.guid, &last_msg).await;
-                                            }
-
-                                            if let Some(log) = job_update.log {
-                                                self.set_job_log(&job_update.guid, &log).await;
-                                            }
-                                        },
-                                        UpdateKind::CrashMsg(crash_msg) => {
-                                            self.new_crash(&crash_msg).await;
-                                        }
-                                    }
-                                }
+                                let seq = update.seq;
+                                self.process_update(update).await;
+
+                                last_seq = seq;
+                                self.set_last_seq(seq).await;
                             },
                             Err(err) => {
-                                self.update_status("down").await;
+                                if let Err(status_err) = self.update_status("down").await {
+                                    error!("{:?}", status_err);
+                                }
                                 return Err(format!(
                                     "agent.UpdatesClient {} throwed an error: {:?}",
                                     self.guid, err
@@ -298,13 +830,40 @@ impl AgentBroker {
                             }
                         }
                     },
-                    None => break
+                    None => {
+                        if let Err(status_err) = self.update_status("down").await {
+                            error!("{:?}", status_err);
+                        }
+                        return Err(format!(
+                            "agent.UpdatesClient {} stream has been closed",
+                            self.guid
+                        ))
+                    }
+                },
+                _ = health_interval.tick() => {
+                    if let Err(err) = self.health_check().await {
+                        if let Err(status_err) = self.update_status("unreachable").await {
+                            error!("{:?}", status_err);
+                        }
+                        return Err(err);
+                    }
+                },
+                _ = reconcile_interval.tick() => {
+                    if let Err(err) = self.reconcile().await {
+                        error!("{:?}", err);
+                    }
+                },
+                _ = ack_interval.tick() => {
+                    if last_seq != acked_seq {
+                        match self.ack_updates(last_seq).await {
+                            Ok(_) => acked_seq = last_seq,
+                            Err(err) => error!("{:?}", err),
+                        }
+                    }
                 }
             }
         }
 
-        self.update_status("down").await;
-
-        Ok(())
+        self.update_status("down").await
     }
 }
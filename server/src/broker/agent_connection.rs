@@ -0,0 +1,83 @@
+use std::fs;
+
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Request, Status};
+
+use crate::config::CONFIG;
+
+pub type AgentChannel = InterceptedService<Channel, AuthInterceptor>;
+
+#[derive(Debug, Clone)]
+pub struct AuthInterceptor {
+    token: Option<MetadataValue<Ascii>>,
+}
+
+impl AuthInterceptor {
+    pub fn new() -> Result<AuthInterceptor, String> {
+        let token = match &CONFIG.agent_auth_token {
+            Some(token) => Some(
+                format!("Bearer {}", token)
+                    .parse::<MetadataValue<Ascii>>()
+                    .map_err(|err| format!("invalid agent auth token: {err}"))?,
+            ),
+            None => None,
+        };
+
+        Ok(AuthInterceptor { token })
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.token {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+fn tls_config() -> Result<Option<ClientTlsConfig>, String> {
+    let ca = match &CONFIG.agent_tls_ca {
+        Some(ca) => ca,
+        None => return Ok(None),
+    };
+
+    let ca = fs::read(ca).map_err(|err| format!("failed to read {ca}: {err}"))?;
+    let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca));
+
+    if let (Some(cert), Some(key)) = (&CONFIG.agent_tls_cert, &CONFIG.agent_tls_key) {
+        let cert = fs::read(cert).map_err(|err| format!("failed to read {cert}: {err}"))?;
+        let key = fs::read(key).map_err(|err| format!("failed to read {key}: {err}"))?;
+        tls = tls.identity(Identity::from_pem(cert, key));
+    }
+
+    if let Some(domain) = &CONFIG.agent_tls_domain {
+        tls = tls.domain_name(domain);
+    }
+
+    Ok(Some(tls))
+}
+
+/// Opens a channel to the agent, every client created on top of it shares the
+/// same connection and presents the auth token if configured
+pub async fn connect(endpoint: &str) -> Result<AgentChannel, String> {
+    let mut endpoint = Endpoint::from_shared(endpoint.to_string())
+        .map_err(|err| format!("invalid endpoint {endpoint}: {err}"))?;
+
+    if let Some(tls) = tls_config()? {
+        endpoint = endpoint
+            .tls_config(tls)
+            .map_err(|err| format!("invalid tls config: {err}"))?;
+    }
+
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|err| format!("couldn't establish connection: {err}"))?;
+
+    Ok(InterceptedService::new(channel, AuthInterceptor::new()?))
+}
@@ -1,5 +1,3 @@
-use log::error;
-
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
@@ -9,16 +7,18 @@ use tokio::{
     task,
 };
 
-use super::agent_broker::{AgentBroker, Request};
+use super::agent_broker::Request;
+use super::agent_supervisor::supervise;
 
 #[derive(Debug)]
 pub enum Event {
     NewAgent { guid: String },
     DelAgent { guid: String },
     AgentRequest { guid: String, request: Box<Request> },
+    ScheduleQueued,
 }
 
-pub async fn broker(db_pool: SqlitePool, mut events: Receiver<Event>) {
+pub async fn broker(db_pool: SqlitePool, mut events: Receiver<Event>, scheduler: Sender<()>) {
     let (disconnect_sender, mut disconnect_receiver) =
         mpsc::channel::<(String, Receiver<Request>)>(100);
     let mut trackers: HashMap<String, Sender<Request>> = HashMap::new();
@@ -30,7 +30,7 @@ pub async fn broker(db_pool: SqlitePool, mut events: Receiver<Event>) {
             },
             disconnect = disconnect_receiver.recv() => {
                 let (guid, _) = disconnect.unwrap();
-                assert!(trackers.remove(&guid).is_some());
+                trackers.remove(&guid);
                 continue;
             }
         };
@@ -44,14 +44,9 @@ pub async fn broker(db_pool: SqlitePool, mut events: Receiver<Event>) {
                     let guid = guid.clone();
                     let disconnect_sender = disconnect_sender.clone();
                     let db_pool = db_pool.clone();
+                    let scheduler = scheduler.clone();
                     task::spawn(async move {
-                        {
-                            let mut agent_broker = AgentBroker::new(guid.clone(), db_pool);
-                            match agent_broker.main(&mut client_receiver).await {
-                                Ok(_) => {}
-                                Err(e) => error!("{}", e),
-                            }
-                        }
+                        supervise(guid.clone(), db_pool, scheduler, &mut client_receiver).await;
                         disconnect_sender
                             .send((guid, client_receiver))
                             .await
@@ -67,6 +62,10 @@ pub async fn broker(db_pool: SqlitePool, mut events: Receiver<Event>) {
                     tracker.send(*request).await.unwrap()
                 }
             }
+            Event::ScheduleQueued => {
+                // A pending wakeup already covers this one
+                _ = scheduler.try_send(());
+            }
         }
     }
 }
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use log::{error, info};
+use sqlx::SqlitePool;
+use tokio::{
+    sync::mpsc::{Receiver, Sender},
+    time,
+};
+
+use super::agent_broker::{AgentBroker, Request};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Keeps an AgentBroker connected to the agent, reconnecting with an exponential
+/// backoff until the agent gets deleted
+pub async fn supervise(
+    guid: String,
+    db_pool: SqlitePool,
+    scheduler: Sender<()>,
+    broker_messages: &mut Receiver<Request>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let mut agent_broker = AgentBroker::new(guid.clone(), db_pool.clone(), scheduler.clone());
+        match agent_broker.main(broker_messages).await {
+            Ok(_) => break,
+            Err(e) => error!("{}", e),
+        }
+
+        if agent_broker.has_connected() {
+            backoff = INITIAL_BACKOFF;
+        }
+
+        info!("Reconnecting to agent {} in {:?}", guid, backoff);
+        let sleep = time::sleep(backoff);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                msg = broker_messages.recv() => match msg {
+                    Some(msg) => agent_broker.reject(msg).await,
+                    None => return,
+                }
+            }
+        }
+
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use log::error;
+use sqlx::SqlitePool;
+use tokio::{
+    sync::mpsc::{Receiver, Sender},
+    time,
+};
+
+use super::agent_broker::Request;
+use super::agent_dispatcher::Event;
+use crate::models::Job;
+
+/// The queue is also checked periodically in case the resources got freed
+/// without waking the scheduler up
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Places the queued jobs whenever the agents free their resources or an
+/// agent comes up
+pub async fn job_scheduler(db_pool: SqlitePool, mut wakeups: Receiver<()>, events: Sender<Event>) {
+    let mut interval = time::interval(SCHEDULE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            wakeup = wakeups.recv() => if wakeup.is_none() {
+                break;
+            },
+        }
+
+        let scheduled_jobs = match Job::schedule_queued(&db_pool).await {
+            Ok(scheduled_jobs) => scheduled_jobs,
+            Err(err) => {
+                error!("Failed to schedule queued jobs: {}", err);
+                continue;
+            }
+        };
+
+        for job in scheduled_jobs {
+            let event = Event::AgentRequest {
+                guid: job.agent_guid,
+                request: Box::new(Request::JobCreate { job: job.request }),
+            };
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+    }
+}
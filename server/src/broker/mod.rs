@@ -1,5 +1,9 @@
 mod agent_broker;
+mod agent_connection;
 mod agent_dispatcher;
+mod agent_supervisor;
+mod job_scheduler;
 
 pub use agent_broker::*;
 pub use agent_dispatcher::*;
+pub use job_scheduler::*;
@@ -9,6 +9,28 @@ pub struct Config {
     pub nfs_dir: String,
     pub tmp_dir: String,
     pub prometheus_url: Option<String>,
+    pub agent_tls_ca: Option<String>,
+    pub agent_tls_cert: Option<String>,
+    pub agent_tls_key: Option<String>,
+    pub agent_tls_domain: Option<String>,
+    pub agent_auth_token: Option<String>,
+    pub agent_join_token: Option<String>,
+    pub admin_password: Option<String>,
+    pub job_retention_days: Option<u64>,
+    #[serde(default)]
+    pub job_retention_keep_crashes: bool,
+    #[serde(default = "default_max_upload_mb")]
+    pub max_upload_mb: u64,
+}
+
+fn default_max_upload_mb() -> u64 {
+    1024
+}
+
+impl Config {
+    pub fn max_upload_size(&self) -> u64 {
+        self.max_upload_mb * 1024 * 1024
+    }
 }
 
 fn init_config() -> Config {
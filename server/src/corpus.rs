@@ -0,0 +1,102 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::config::CONFIG;
+use crate::utils::{get_job_dir, link_or_copy};
+
+pub fn corpus_path(guid: &str) -> PathBuf {
+    Path::new(&CONFIG.nfs_dir)
+        .join("corpora")
+        .join(format!("{}.zip", guid))
+}
+
+/// Where the agent leaves the corpus minimized with afl-cmin
+fn minimized_path(job_guid: &str, corpus_guid: &str) -> PathBuf {
+    get_job_dir(job_guid).join(format!("cmin-{}.zip", corpus_guid))
+}
+
+/// Packs the queues synced into res/ by schedule.py into a zip usable as
+/// corpus.zip, the entries are named the same way seed_findings does.
+/// Returns the amount of packed entries
+pub fn zip_findings(job_guid: &str, dst: &Path) -> Result<usize> {
+    let mut zip = ZipWriter::new(fs::File::create(dst)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut count = 0;
+    let res_dir = get_job_dir(job_guid).join("res");
+    if res_dir.is_dir() {
+        for sync_dir in fs::read_dir(res_dir)? {
+            let sync_dir = sync_dir?.path();
+            let queue = sync_dir.join("queue");
+            if !queue.is_dir() {
+                continue;
+            }
+
+            let sync_name = sync_dir.file_name().unwrap_or_default().to_string_lossy();
+            for entry in fs::read_dir(queue)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                zip.start_file(format!("in/{}_{}", sync_name, name), options)?;
+                io::copy(&mut fs::File::open(&path)?, &mut zip)?;
+                count += 1;
+            }
+        }
+    }
+
+    zip.finish()?.flush()?;
+    Ok(count)
+}
+
+/// Stores the findings of the job as the corpus. Returns the size of the
+/// stored zip, None if the job has no findings
+pub fn store_findings(job_guid: &str, corpus_guid: &str) -> Result<Option<u64>> {
+    let path = corpus_path(corpus_guid);
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // Written aside first so a half-written corpus is never picked up
+    let part = path.with_extension("part");
+    match zip_findings(job_guid, &part) {
+        Ok(0) => {
+            fs::remove_file(&part)?;
+            Ok(None)
+        }
+        Ok(_) => {
+            fs::rename(&part, &path)?;
+            Ok(Some(fs::metadata(&path)?.len()))
+        }
+        Err(err) => {
+            _ = fs::remove_file(&part);
+            Err(err)
+        }
+    }
+}
+
+/// Moves the zip written by cmin.py into the corpora. Returns its size
+pub fn store_minimized(job_guid: &str, corpus_guid: &str) -> io::Result<u64> {
+    let path = corpus_path(corpus_guid);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::rename(minimized_path(job_guid, corpus_guid), &path)?;
+    fs::metadata(&path).map(|metadata| metadata.len())
+}
+
+/// Puts the stored corpus into the job directory
+pub fn link_corpus(corpus_guid: &str, dst: &Path) -> io::Result<()> {
+    link_or_copy(&corpus_path(corpus_guid), dst)
+}
+
+pub fn remove_corpus(corpus_guid: &str) -> io::Result<()> {
+    match fs::remove_file(corpus_path(corpus_guid)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
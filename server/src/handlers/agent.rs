@@ -1,13 +1,24 @@
+use std::collections::BTreeMap;
+
+use crate::auth::request_token;
 use crate::broker::Event;
-use crate::models::{Agent, AgentCreateRequest};
+use crate::config::CONFIG;
+use crate::models::{
+    validate_labels, Agent, AgentCreateRequest, AgentRegisterRequest, AgentRegisterResponse,
+    AgentStateRequest, FuzzerOptions, Labels, User, AGENT_STATES,
+};
 use crate::utils::notify_processor;
 
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
-use log::error;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use log::{error, info};
 use sqlx::SqlitePool;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
+/// Carries the per-agent secret of the heartbeats, the join token is shared
+/// by all the agents
+const AGENT_SECRET_HEADER: &str = "X-Agent-Secret";
+
 #[derive(Debug, Default)]
 pub struct JobInfo {
     pub guid: String,
@@ -21,6 +32,17 @@ pub struct JobInfo {
     pub target: String,
     pub corpus: String,
     pub crash_auto_analyze: bool,
+    pub created_by: String,
+    pub priority: i64,
+    pub strategy: String,
+    pub min_cores: u64,
+    pub pinned_agents: Vec<String>,
+    pub constraints: Labels,
+    pub env: BTreeMap<String, String>,
+    pub fuzzer: FuzzerOptions,
+    /// Hashes of the stored artifacts the job refers to, by the job file they
+    /// are linked as
+    pub artifacts: BTreeMap<String, String>,
 }
 
 #[get("/agents")]
@@ -49,11 +71,16 @@ async fn get_by_guid(guid: web::Path<String>, db_pool: web::Data<SqlitePool>) ->
 
 #[post("/agent")]
 async fn create(
+    user: User,
     agent_req: web::Json<AgentCreateRequest>,
     db_pool: web::Data<SqlitePool>,
     tx: web::Data<Sender<Event>>,
 ) -> impl Responder {
     let agent_req = agent_req.into_inner();
+    if let Err(err) = validate_labels(&agent_req.labels) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+
     let agent = match agent_req.agent_type.as_ref() {
         "linux" => Agent {
             guid: Uuid::new_v4().to_string(),
@@ -61,6 +88,8 @@ async fn create(
             agent_type: agent_req.agent_type,
             endpoint: agent_req.endpoint,
             status: "init".to_string(),
+            created_by: user.username,
+            labels: agent_req.labels,
             ..Default::default()
         },
         _ => {
@@ -86,6 +115,178 @@ async fn create(
     }
 }
 
+/// Checks the join token the agents register themselves with
+fn check_join_token(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let token = match &CONFIG.agent_join_token {
+        Some(token) => token,
+        None => return Err(HttpResponse::Forbidden().body("Agent self-registration is disabled")),
+    };
+
+    match request_token(req) {
+        Some(value) if &value == token => Ok(()),
+        _ => Err(HttpResponse::Unauthorized().body("Invalid join token")),
+    }
+}
+
+#[post("/agent/register")]
+async fn register(
+    req: HttpRequest,
+    agent_req: web::Json<AgentRegisterRequest>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    if let Err(response) = check_join_token(&req) {
+        return response;
+    }
+
+    let agent_req = agent_req.into_inner();
+    if agent_req.agent_type != "linux" {
+        return HttpResponse::BadRequest().body("Unsupported agent type");
+    }
+    if let Err(err) = validate_labels(&agent_req.labels) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+
+    // The join token is shared, so an existing agent is only updated by the
+    // one holding its secret. The server assigns the guid of a new agent
+    let known = match &agent_req.guid {
+        Some(guid) => {
+            let secret = agent_req.secret.as_deref().unwrap_or_default();
+            match Agent::verify_secret(guid, secret, db_pool.get_ref()).await {
+                Ok(Some(true)) => Some(guid.clone()),
+                Ok(Some(false)) => {
+                    return HttpResponse::Conflict().body("The agent is registered by another host")
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    error!("error verifying agent secret: {}", err);
+                    return HttpResponse::InternalServerError()
+                        .body("Error trying to register agent");
+                }
+            }
+        }
+        None => None,
+    };
+    let (guid, secret) = match known {
+        Some(guid) => (guid, None),
+        None => (Uuid::new_v4().to_string(), Some(Agent::new_secret())),
+    };
+
+    match Agent::register(&guid, secret.as_deref(), &agent_req, db_pool.get_ref()).await {
+        Ok(_) => {
+            info!(
+                "Agent {} registered from {} running version {}",
+                guid, agent_req.endpoint, agent_req.version
+            );
+            notify_processor(&tx.into_inner(), Event::NewAgent { guid: guid.clone() }).await;
+            HttpResponse::Ok().json(AgentRegisterResponse { guid, secret })
+        }
+        Err(err) => {
+            error!("error registering agent: {}", err);
+            HttpResponse::InternalServerError().body("Error trying to register agent")
+        }
+    }
+}
+
+#[post("/agent/{guid}/heartbeat")]
+async fn heartbeat(
+    req: HttpRequest,
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(response) = check_join_token(&req) {
+        return response;
+    }
+
+    let secret = req
+        .headers()
+        .get(AGENT_SECRET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    match Agent::verify_secret(&guid, secret, db_pool.get_ref()).await {
+        Ok(Some(true)) => {}
+        Ok(Some(false)) => return HttpResponse::Forbidden().body("Invalid agent secret"),
+        Ok(None) => return HttpResponse::NotFound().body("Agent not found"),
+        Err(err) => {
+            error!("error verifying agent secret: {}", err);
+            return HttpResponse::InternalServerError()
+                .body("Error trying to update agent heartbeat");
+        }
+    }
+
+    match Agent::touch(&guid, db_pool.get_ref()).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().body("Agent not found"),
+        Err(err) => {
+            error!("Failed to update agent heartbeat: {}", err);
+            HttpResponse::InternalServerError().body("Error trying to update agent heartbeat")
+        }
+    }
+}
+
+#[put("/agent/{guid}/labels")]
+async fn set_labels(
+    guid: web::Path<String>,
+    labels: web::Json<Labels>,
+    db_pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(err) = validate_labels(&labels) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+
+    match Agent::set_labels(&guid, &labels, db_pool.get_ref()).await {
+        Ok(true) => HttpResponse::Ok().json(labels.into_inner()),
+        Ok(false) => HttpResponse::NotFound().body("Agent not found"),
+        Err(err) => {
+            error!("Failed to set agent labels: {}", err);
+            HttpResponse::InternalServerError().body("Error trying to set agent labels")
+        }
+    }
+}
+
+#[put("/agent/{guid}/state")]
+async fn set_state(
+    guid: web::Path<String>,
+    req: web::Json<AgentStateRequest>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    if !AGENT_STATES.contains(&req.state.as_str()) {
+        return HttpResponse::BadRequest().body("Unknown agent state");
+    }
+
+    if req.state == "maintenance" {
+        match Agent::count_running_jobs(&guid, db_pool.get_ref()).await {
+            Ok(0) => {}
+            Ok(running) => {
+                return HttpResponse::Conflict().body(format!(
+                    "Agent still runs {} sub-jobs, drain it first",
+                    running
+                ))
+            }
+            Err(err) => {
+                error!("Failed to count agent jobs: {}", err);
+                return HttpResponse::InternalServerError()
+                    .body("Error trying to count agent jobs");
+            }
+        }
+    }
+
+    match Agent::set_state(&guid, &req.state, db_pool.get_ref()).await {
+        Ok(Some(state)) => {
+            if state == "active" {
+                notify_processor(&tx.into_inner(), Event::ScheduleQueued).await;
+            }
+            HttpResponse::Ok().json(AgentStateRequest { state })
+        }
+        Ok(None) => HttpResponse::NotFound().body("Agent not found"),
+        Err(err) => {
+            error!("Failed to set agent state: {}", err);
+            HttpResponse::InternalServerError().body("Error trying to set agent state")
+        }
+    }
+}
+
 #[delete("/agent/{id}")]
 async fn delete(
     guid: web::Path<String>,
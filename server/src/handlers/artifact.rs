@@ -0,0 +1,60 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use futures::StreamExt;
+use log::{error, info};
+use sqlx::SqlitePool;
+
+use crate::artifact::store_upload;
+use crate::models::{Artifact, User};
+
+#[get("/artifacts")]
+async fn get_artifacts(db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match Artifact::get_all(db_pool.get_ref()).await {
+        Ok(artifacts) => HttpResponse::Ok().json(artifacts),
+        Err(err) => {
+            error!("Error fetching artifacts: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching artifacts")
+        }
+    }
+}
+
+/// Stores the uploaded target.zip or corpus.zip, the jobs can then refer to
+/// it by the returned hash
+#[post("/artifact")]
+async fn upload_artifact(
+    user: User,
+    mut payload: Multipart,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, Error> {
+    let field = match payload.next().await {
+        Some(field) => field?,
+        None => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "you haven't specified the artifact",
+            ))
+        }
+    };
+
+    let (hash, size) = store_upload(field).await?;
+    let size = i64::try_from(size).unwrap_or(0);
+    if let Err(err) = Artifact::store(&hash, size, &user.username, db_pool.get_ref()).await {
+        error!("Error storing artifact: {}", err);
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Error storing artifact",
+        ));
+    }
+
+    match Artifact::find(&hash, db_pool.get_ref()).await {
+        Ok(Some(artifact)) => {
+            info!("Stored artifact {}", hash);
+            Ok(HttpResponse::Ok().json(artifact))
+        }
+        Ok(None) => Err(actix_web::error::ErrorNotFound("Artifact not found")),
+        Err(err) => {
+            error!("Error fetching artifact: {}", err);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Error fetching artifact",
+            ))
+        }
+    }
+}
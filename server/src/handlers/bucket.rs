@@ -0,0 +1,27 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use log::error;
+use sqlx::SqlitePool;
+
+use crate::models::Bucket;
+
+#[get("/buckets")]
+async fn get_buckets(db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match Bucket::get_all(db_pool.get_ref()).await {
+        Ok(buckets) => HttpResponse::Ok().json(buckets),
+        Err(err) => {
+            error!("Error fetching buckets: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching buckets")
+        }
+    }
+}
+
+#[get("/bucket/{id}")]
+async fn get_bucket(id: web::Path<i64>, db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match Bucket::get_bucket(id.into_inner(), db_pool.get_ref()).await {
+        Ok(bucket) => HttpResponse::Ok().json(bucket),
+        Err(err) => {
+            error!("Error fetching bucket: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching bucket")
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::{
+    delete, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    post, web, Error, HttpResponse, Responder,
+};
+use futures::StreamExt;
+use log::{error, info};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::corpus::{corpus_path, remove_corpus};
+use crate::handlers::job::fetch_file;
+use crate::models::{Corpus, User};
+
+#[get("/corpora")]
+async fn get_corpora(db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match Corpus::get_all(db_pool.get_ref()).await {
+        Ok(corpora) => HttpResponse::Ok().json(corpora),
+        Err(err) => {
+            error!("Error fetching corpora: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching corpora")
+        }
+    }
+}
+
+/// Stores an uploaded corpus.zip under a name the jobs can refer to
+#[post("/corpus")]
+async fn upload_corpus(
+    user: User,
+    mut payload: Multipart,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, Error> {
+    let mut corpus = Corpus {
+        guid: Uuid::new_v4().to_string(),
+        creation_date: chrono::offset::Utc::now().to_string(),
+        created_by: user.username,
+        status: "ready".to_string(),
+        ..Default::default()
+    };
+    let path = corpus_path(&corpus.guid);
+    let part = path.with_extension("part");
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    let mut uploaded = false;
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+        let name = field
+            .content_disposition()
+            .get_name()
+            .unwrap_or_default()
+            .to_string();
+        match name.as_ref() {
+            "corpus" => {
+                if let Err(err) = fetch_file(field, &part).await {
+                    _ = fs::remove_file(&part);
+                    return Err(err);
+                }
+                uploaded = true;
+            }
+            "name" | "description" => {
+                let mut value = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    value.extend_from_slice(&chunk?);
+                }
+                let value = String::from_utf8_lossy(&value).trim().to_string();
+                if name == "name" {
+                    corpus.name = value;
+                } else {
+                    corpus.description = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let invalid = if corpus.name.is_empty() {
+        Some("you haven't specified the corpus name")
+    } else if !uploaded {
+        Some("you haven't specified corpus.zip")
+    } else {
+        None
+    };
+    if let Some(invalid) = invalid {
+        _ = fs::remove_file(&part);
+        return Err(actix_web::error::ErrorBadRequest(invalid));
+    }
+
+    match Corpus::find(&corpus.name, db_pool.get_ref()).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            _ = fs::remove_file(&part);
+            return Ok(HttpResponse::Conflict().body("Corpus with this name already exists"));
+        }
+        Err(err) => {
+            _ = fs::remove_file(&part);
+            error!("Error fetching corpus: {}", err);
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Error fetching corpus",
+            ));
+        }
+    }
+
+    fs::rename(&part, &path)?;
+    corpus.size = i64::try_from(fs::metadata(&path)?.len()).unwrap_or(0);
+
+    if let Err(err) = Corpus::create(&corpus, db_pool.get_ref()).await {
+        _ = remove_corpus(&corpus.guid);
+        error!("Error creating corpus: {}", err);
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Error creating corpus",
+        ));
+    }
+
+    info!("Stored corpus {} as {}", corpus.name, corpus.guid);
+    Ok(HttpResponse::Ok().json(corpus))
+}
+
+#[get("/corpus/{guid}")]
+async fn get_corpus(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<NamedFile, Error> {
+    let corpus = match Corpus::find(&guid, db_pool.get_ref()).await {
+        Ok(Some(corpus)) if corpus.status == "ready" => corpus,
+        Ok(_) => return Err(actix_web::error::ErrorNotFound("Corpus not found")),
+        Err(err) => {
+            error!("Error fetching corpus: {}", err);
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Error fetching corpus",
+            ));
+        }
+    };
+
+    Ok(
+        NamedFile::open(corpus_path(&corpus.guid))?.set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{}.zip", corpus.name))],
+        }),
+    )
+}
+
+#[delete("/corpus/{guid}")]
+async fn delete_corpus(guid: web::Path<String>, db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match Corpus::delete(&guid, db_pool.get_ref()).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("Corpus not found"),
+        Err(err) => {
+            error!("Error deleting corpus: {}", err);
+            return HttpResponse::InternalServerError().body("Error deleting corpus");
+        }
+    }
+
+    // The jobs using the corpus keep their own links to it
+    match remove_corpus(&guid) {
+        Ok(_) => HttpResponse::Ok().body(guid.into_inner()),
+        Err(err) => {
+            error!("Error removing corpus {}: {}", guid, err);
+            HttpResponse::InternalServerError().body("Error removing corpus")
+        }
+    }
+}
@@ -4,13 +4,17 @@ use actix_http::header::ExtendedValue;
 use actix_web::{
     get,
     http::header::{self, DispositionParam, DispositionType},
-    web, Error, HttpResponse, Responder,
+    post, web, Error, HttpResponse, Responder,
 };
 use log::{error, info};
 use prost::Message;
 use sqlx::SqlitePool;
+use tokio::sync::{mpsc::Sender, oneshot};
 
-use crate::{models::Crash, utils::get_job_dir};
+use crate::broker::{Event, Request};
+use crate::models::{Agent, Crash, Job};
+use crate::protos::agent::{AnalyzeRequest, MinimizeRequest};
+use crate::utils::{get_job_dir, notify_processor};
 
 #[get("/crashes")]
 async fn get_crashes(db_pool: web::Data<SqlitePool>) -> impl Responder {
@@ -58,9 +62,30 @@ async fn get_crash(
         }
     };
 
-    let crash_path = get_job_dir(&crash_info.collection_guid)
-        .join("crashes")
-        .join(&crash_info.name);
+    crash_file(&crash_info.collection_guid, &crash_info.name)
+}
+
+#[get("/crash/{guid}/minimized")]
+async fn get_minimized_crash(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, Error> {
+    let crash_info = match Crash::get_crash_info(&guid, db_pool.get_ref()).await {
+        Ok(crash) => crash,
+        Err(err) => {
+            error!("Error fetching crash info: {}", err);
+            return Err(actix_web::error::ErrorBadGateway(err));
+        }
+    };
+
+    match &crash_info.minimized {
+        Some(minimized) => crash_file(&crash_info.collection_guid, minimized),
+        None => Ok(HttpResponse::NotFound().body("Crash hasn't been minimized")),
+    }
+}
+
+fn crash_file(job_guid: &str, name: &str) -> Result<HttpResponse, Error> {
+    let crash_path = get_job_dir(job_guid).join("crashes").join(name);
     info!("Using path {:?}", crash_path);
     let content = fs::read(crash_path)?;
     Ok(HttpResponse::Ok()
@@ -69,9 +94,161 @@ async fn get_crash(
             parameters: vec![DispositionParam::FilenameExt(ExtendedValue {
                 charset: header::Charset::Iso_8859_1,
                 language_tag: None,
-                value: crash_info.name.encode_to_vec(),
+                value: name.to_string().encode_to_vec(),
             })],
         })
         .content_type("blob")
         .body(content))
 }
+
+#[post("/crash/{guid}/analyze")]
+async fn analyze_crash(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let crash = match Crash::get_crash_info(&guid, db_pool.get_ref()).await {
+        Ok(crash) => crash,
+        Err(err) => {
+            error!("Error fetching crash info: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching crash info");
+        }
+    };
+
+    let job = match Job::get_job(&crash.collection_guid, db_pool.get_ref()).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching job");
+        }
+    };
+
+    let agent_guid = match Agent::pick_for_job(&crash.collection_guid, db_pool.get_ref()).await {
+        Ok(Some(agent_guid)) => agent_guid,
+        Ok(None) => {
+            return HttpResponse::ServiceUnavailable().body("No agent is available for analysis")
+        }
+        Err(err) => {
+            error!("Error picking an agent: {}", err);
+            return HttpResponse::InternalServerError().body("Error picking an agent");
+        }
+    };
+
+    let (cpus, ram) = job.task_limits();
+    let (sender, receiver) = oneshot::channel();
+    notify_processor(
+        &tx.into_inner(),
+        Event::AgentRequest {
+            guid: agent_guid,
+            request: Box::new(Request::CrashAnalyze {
+                request: AnalyzeRequest {
+                    job_guid: crash.collection_guid.clone(),
+                    crash_guid: crash.guid.clone(),
+                    name: crash.name.clone(),
+                    image: job.job_collection.image,
+                    cpus,
+                    ram,
+                    fuzzer: Some((&job.job_collection.fuzzer).into()),
+                    env: job.job_collection.env.into_iter().collect(),
+                },
+                sender,
+            }),
+        },
+    )
+    .await;
+
+    let analyzed = match receiver.await {
+        Ok(Ok(analyzed)) => analyzed,
+        Ok(Err(err)) => {
+            error!("Error analyzing crash {}: {}", crash.guid, err);
+            return HttpResponse::InternalServerError().body(err);
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("Analysis request has been dropped")
+        }
+    };
+
+    if let Err(err) = Crash::set_analyzed(&crash.guid, &analyzed, db_pool.get_ref()).await {
+        error!("Error saving crash analysis: {}", err);
+        return HttpResponse::InternalServerError().body("Error saving crash analysis");
+    }
+
+    match Crash::get_crash_info(&crash.guid, db_pool.get_ref()).await {
+        Ok(crash) => HttpResponse::Ok().json(crash),
+        Err(err) => {
+            error!("Error fetching crash info: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching crash info")
+        }
+    }
+}
+
+#[post("/crash/{guid}/minimize")]
+async fn minimize_crash(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let crash = match Crash::get_crash_info(&guid, db_pool.get_ref()).await {
+        Ok(crash) => crash,
+        Err(err) => {
+            error!("Error fetching crash info: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching crash info");
+        }
+    };
+
+    let job = match Job::get_job(&crash.collection_guid, db_pool.get_ref()).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching job");
+        }
+    };
+
+    let agent_guid = match Agent::pick_for_job(&crash.collection_guid, db_pool.get_ref()).await {
+        Ok(Some(agent_guid)) => agent_guid,
+        Ok(None) => {
+            return HttpResponse::ServiceUnavailable()
+                .body("No agent is available for minimization")
+        }
+        Err(err) => {
+            error!("Error picking an agent: {}", err);
+            return HttpResponse::InternalServerError().body("Error picking an agent");
+        }
+    };
+
+    if let Err(err) = Crash::set_minimize_status(
+        &crash.guid,
+        "running",
+        "Minimization has been requested",
+        None,
+        db_pool.get_ref(),
+    )
+    .await
+    {
+        error!("Error updating crash: {}", err);
+        return HttpResponse::InternalServerError().body("Error updating crash");
+    }
+
+    let (cpus, ram) = job.task_limits();
+    notify_processor(
+        &tx.into_inner(),
+        Event::AgentRequest {
+            guid: agent_guid,
+            request: Box::new(Request::CrashMinimize {
+                request: MinimizeRequest {
+                    job_guid: crash.collection_guid,
+                    crash_guid: crash.guid,
+                    name: crash.name,
+                    image: job.job_collection.image,
+                    cpus,
+                    ram,
+                    fuzzer: Some((&job.job_collection.fuzzer).into()),
+                    env: job.job_collection.env.into_iter().collect(),
+                },
+            }),
+        },
+    )
+    .await;
+
+    HttpResponse::Accepted().body("Minimization has been requested")
+}
@@ -1,165 +1,410 @@
 use std::io::prelude::*;
-use std::{fs, path::Path};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use crate::artifact::{link_artifact, store_file, store_upload, upload_too_large};
 use crate::broker::{Event, Request};
 use crate::config::CONFIG;
+use crate::corpus::{link_corpus, remove_corpus, store_findings, zip_findings};
 use crate::handlers::agent::JobInfo;
-use crate::models::{Crash, Job};
-use crate::utils::notify_processor;
+use crate::job_spec::{validate_job, CorpusRef, JobSpec, SpecErrors, JOB_SPEC_SCHEMA};
+use crate::models::{
+    parse_labels, valid_hash, Agent, Artifact, Corpus, CorpusSaveRequest, Crash, Job, User,
+};
+use crate::protos::agent::CorpusMinimizeRequest;
+use crate::retention::purge_job;
+use crate::scheduler::DEFAULT_STRATEGY;
+use crate::utils::{get_job_dir, link_or_copy, notify_processor};
 
+use actix_files::NamedFile;
 use actix_multipart::{Field, Multipart};
-use actix_web::{get, post, web, Error, HttpResponse, Responder};
-use futures::StreamExt;
+use actix_web::{
+    delete, get, http::header, post, web, Error, HttpRequest, HttpResponse, Responder,
+};
+use futures::{stream, StreamExt};
 use log::{error, info};
+use serde::Deserialize;
 use sqlx::SqlitePool;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{self, Sender};
 use uuid::Uuid;
 
-async fn fetch_file(mut field: Field, path: &Path) -> Result<(), Error> {
+#[derive(Deserialize)]
+pub struct LogStreamQuery {
+    pub idx: u64,
+}
+
+#[derive(Deserialize)]
+pub struct JobDeleteQuery {
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    keep_crashes: bool,
+}
+
+#[derive(Deserialize)]
+pub struct JobSpecQuery {
+    /// json or yaml
+    pub format: Option<String>,
+}
+
+/// Settings of the cloned job which differ from the original one
+#[derive(Deserialize, Default)]
+pub struct JobCloneRequest {
+    pub name: Option<String>,
+    pub cpus: Option<u64>,
+    pub ram: Option<u64>,
+    pub timeout: Option<String>,
+}
+
+pub(crate) async fn fetch_file(mut field: Field, path: &Path) -> Result<(), Error> {
     let mut target = fs::File::create(path)?;
+    let mut size = 0;
     while let Some(chunk) = field.next().await {
-        target.write_all(&chunk?)?;
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        if size > CONFIG.max_upload_size() {
+            return Err(upload_too_large());
+        }
+        target.write_all(&chunk)?;
     }
     Ok(())
 }
 
-async fn process_job_create(payload: &mut Multipart, job_dir: &Path) -> Result<JobInfo, Error> {
-    let mut job_info = JobInfo {
-        ..Default::default()
-    };
-    while let Some(item) = payload.next().await {
-        let mut field = item?;
+/// Stores the uploaded file as an artifact and links it into the job.
+/// Returns the hash of the artifact
+async fn use_upload(
+    field: Field,
+    dst: &Path,
+    created_by: &str,
+    db_pool: &SqlitePool,
+) -> Result<String, Error> {
+    let (hash, size) = store_upload(field).await?;
+    let size = i64::try_from(size).unwrap_or(0);
+    if let Err(err) = Artifact::store(&hash, size, created_by, db_pool).await {
+        error!("Error storing artifact: {}", err);
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Error storing artifact",
+        ));
+    }
 
-        let name = field.content_disposition().get_name().unwrap().to_string();
-        match name.as_ref() {
-            "target" => {
-                fetch_file(field, &job_dir.join("target.zip")).await?;
-                job_info.target = "target.zip".to_string();
-            }
-            "corpus" => {
-                fetch_file(field, &job_dir.join("corpus.zip")).await?;
-                job_info.corpus = "corpus.zip".to_string();
-            }
-            _ => {
-                let chunk = field.next().await.unwrap()?;
-                match name.as_ref() {
-                    "name" => {
-                        job_info.name = std::str::from_utf8(&chunk).unwrap_or("").to_string();
-                    }
-                    "description" => {
-                        job_info.description = std::str::from_utf8(&chunk).unwrap().to_string();
-                    }
-                    "agent-type" => {
-                        job_info.agent_type = std::str::from_utf8(&chunk).unwrap_or("").to_string();
-                    }
-                    "image" => {
-                        job_info.image = std::str::from_utf8(&chunk).unwrap_or("").to_string();
-                    }
-                    "cpus" => {
-                        job_info.cpus =
-                            std::str::from_utf8(&chunk).unwrap().parse::<u64>().unwrap();
-                    }
-                    "ram" => {
-                        job_info.ram = std::str::from_utf8(&chunk).unwrap().parse::<u64>().unwrap();
-                    }
-                    "timeout" => {
-                        job_info.timeout = std::str::from_utf8(&chunk).unwrap().to_string();
-                    }
-                    "crash-auto-analyze" => {
-                        job_info.crash_auto_analyze = std::str::from_utf8(&chunk)
-                            .unwrap()
-                            .parse::<bool>()
-                            .unwrap();
-                    }
-                    _ => {}
-                }
-            }
+    link_artifact(&hash, dst)?;
+    Ok(hash)
+}
+
+/// Links the artifact referred to by its hash into the job
+async fn use_artifact(hash: &str, dst: &Path, db_pool: &SqlitePool) -> Result<(), Error> {
+    if !valid_hash(hash) {
+        return Err(actix_web::error::ErrorBadRequest("invalid artifact hash"));
+    }
+
+    match Artifact::touch(hash, db_pool).await {
+        Ok(true) => {
+            link_artifact(hash, dst)?;
+            Ok(())
+        }
+        Ok(false) => Err(actix_web::error::ErrorBadRequest(format!(
+            "artifact {} is not found",
+            hash
+        ))),
+        Err(err) => {
+            error!("Error fetching artifact: {}", err);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Error fetching artifact",
+            ))
         }
     }
-    Ok(job_info)
 }
 
-fn sanitize_job_info(job_info: &JobInfo) -> Result<(), Error> {
-    if job_info.agent_type == "linux" && job_info.image.is_empty() {
-        return Err(actix_web::error::ErrorBadRequest(
-            "you haven't specified image",
-        ));
+/// Links the stored corpus referred to by its name or guid into the job
+async fn use_stored_corpus(
+    reference: &str,
+    job_dir: &Path,
+    db_pool: &SqlitePool,
+) -> Result<(), Error> {
+    match Corpus::find(reference, db_pool).await {
+        Ok(Some(corpus)) if corpus.status == "ready" => {
+            link_corpus(&corpus.guid, &job_dir.join("corpus.zip"))?;
+            Ok(())
+        }
+        Ok(_) => Err(actix_web::error::ErrorBadRequest(format!(
+            "corpus {} is not found",
+            reference
+        ))),
+        Err(err) => {
+            error!("Error fetching corpus: {}", err);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Error fetching corpus",
+            ))
+        }
     }
+}
 
-    if job_info.cpus == 0 {
-        return Err(actix_web::error::ErrorBadRequest(
-            "you haven't number of cpu cores",
-        ));
+/// Seeds the job with the findings of a previous job
+fn use_findings(job_guid: &str, job_dir: &Path) -> Result<(), Error> {
+    if Uuid::parse_str(job_guid).is_err() {
+        return Err(actix_web::error::ErrorBadRequest("invalid corpus job"));
     }
 
-    if job_info.timeout.is_empty() && job_info.timeout.parse::<humantime::Duration>().is_err() {
-        return Err(actix_web::error::ErrorBadRequest("invalid timeout format"));
+    match zip_findings(job_guid, &job_dir.join("corpus.zip")) {
+        Ok(count) if count > 0 => Ok(()),
+        _ => Err(actix_web::error::ErrorBadRequest(format!(
+            "job {} has no findings",
+            job_guid
+        ))),
     }
+}
 
-    if job_info.target.is_empty() {
-        return Err(actix_web::error::ErrorBadRequest(
-            "you haven't specified target.zip",
+/// Validates the job on top of the errors of its parsing
+async fn check_job(
+    job_info: &JobInfo,
+    data_dir: &Path,
+    mut errors: SpecErrors,
+    db_pool: &SqlitePool,
+) -> Result<(), Error> {
+    if let Err(err) = validate_job(job_info, data_dir, &mut errors, db_pool).await {
+        error!("Error validating job: {}", err);
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Error validating job",
         ));
     }
+    errors.into_result().map_err(Error::from)
+}
 
-    if job_info.corpus.is_empty() {
-        return Err(actix_web::error::ErrorBadRequest(
-            "you haven't specified corpus.zip",
-        ));
+/// Size limit of the text fields of the job form
+const MAX_FIELD_SIZE: usize = 64 * 1024;
+
+async fn read_text(field: &mut Field) -> Result<String, Error> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        value.extend_from_slice(&chunk?);
+        if value.len() > MAX_FIELD_SIZE {
+            return Err(actix_web::error::ErrorPayloadTooLarge(format!(
+                "the value exceeds {} bytes",
+                MAX_FIELD_SIZE
+            )));
+        }
     }
+    String::from_utf8(value)
+        .map_err(|_| actix_web::error::ErrorBadRequest("the value isn't valid UTF-8"))
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, message: &str) -> Result<T, Error> {
+    value
+        .parse::<T>()
+        .map_err(|_| actix_web::error::ErrorBadRequest(message.to_string()))
+}
+
+/// Turns the client errors of the field into field errors, so every failing
+/// field is reported at once. The server errors stop the parsing
+fn field_result<T>(
+    errors: &mut SpecErrors,
+    field: &str,
+    result: Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.as_response_error().status_code().is_client_error() => {
+            errors.add(field, err.to_string());
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
 
+async fn apply_field(
+    job_info: &mut JobInfo,
+    name: &str,
+    value: &str,
+    job_dir: &Path,
+    db_pool: &SqlitePool,
+) -> Result<(), Error> {
+    match name {
+        "name" => job_info.name = value.to_string(),
+        "description" => job_info.description = value.to_string(),
+        "agent-type" => job_info.agent_type = value.to_string(),
+        "image" => job_info.image = value.to_string(),
+        "cpus" => job_info.cpus = parse_value(value, "must be a positive integer")?,
+        "ram" => job_info.ram = parse_value(value, "must be a non-negative integer")?,
+        "timeout" => job_info.timeout = value.to_string(),
+        "target-artifact" => {
+            use_artifact(value, &job_dir.join("target.zip"), db_pool).await?;
+            job_info.target = "target.zip".to_string();
+            job_info
+                .artifacts
+                .insert(job_info.target.clone(), value.to_string());
+        }
+        "corpus-artifact" => {
+            use_artifact(value, &job_dir.join("corpus.zip"), db_pool).await?;
+            job_info.corpus = "corpus.zip".to_string();
+            job_info
+                .artifacts
+                .insert(job_info.corpus.clone(), value.to_string());
+        }
+        "corpus-name" => {
+            use_stored_corpus(value, job_dir, db_pool).await?;
+            job_info.corpus = "corpus.zip".to_string();
+        }
+        "corpus-job" => {
+            use_findings(value, job_dir)?;
+            job_info.corpus = "corpus.zip".to_string();
+        }
+        "strategy" => job_info.strategy = value.to_string(),
+        "min-cores" => job_info.min_cores = parse_value(value, "must be a positive integer")?,
+        "constraints" => {
+            job_info.constraints = parse_labels(value).map_err(actix_web::error::ErrorBadRequest)?
+        }
+        "agents" => {
+            job_info.pinned_agents = value
+                .split(',')
+                .map(|guid| guid.trim().to_string())
+                .filter(|guid| !guid.is_empty())
+                .collect()
+        }
+        "priority" => job_info.priority = parse_value(value, "must be an integer")?,
+        "fuzzer-mode" => job_info.fuzzer.mode = value.to_string(),
+        "fuzzer-dict" => job_info.fuzzer.dict = value.to_string(),
+        "fuzzer-input" => {
+            job_info.fuzzer.input_file = match value {
+                "file" => true,
+                "stdin" => false,
+                _ => return Err(actix_web::error::ErrorBadRequest("must be stdin or file")),
+            }
+        }
+        "fuzzer-args" => {
+            job_info.fuzzer.extra_args = value.split_whitespace().map(str::to_string).collect()
+        }
+        "fuzzer-schedules" => {
+            job_info.fuzzer.schedules = value
+                .split(',')
+                .map(|schedule| schedule.trim().to_string())
+                .filter(|schedule| !schedule.is_empty())
+                .collect()
+        }
+        "crash-auto-analyze" => {
+            job_info.crash_auto_analyze = parse_value(value, "must be true or false")?
+        }
+        _ => {}
+    }
     Ok(())
 }
 
-#[post("/job")]
-async fn create_job(
-    mut payload: Multipart,
-    db_pool: web::Data<SqlitePool>,
-    tx: web::Data<Sender<Event>>,
-) -> Result<HttpResponse, Error> {
-    let mut job_info;
-    let guid = Uuid::new_v4().to_string();
-    let job_tmp_dir = Path::new(&CONFIG.tmp_dir).join(&guid);
-    let job_nfs_dir = Path::new(&CONFIG.nfs_dir).join("jobs").join(&guid);
-    let data_dir = job_tmp_dir.join("data/");
+/// Parses the job form into the job info, the failing fields are collected
+/// into the errors instead of stopping at the first one
+async fn process_job_create(
+    payload: &mut Multipart,
+    job_dir: &Path,
+    created_by: &str,
+    errors: &mut SpecErrors,
+    db_pool: &SqlitePool,
+) -> Result<JobInfo, Error> {
+    let mut job_info = JobInfo {
+        strategy: DEFAULT_STRATEGY.to_string(),
+        min_cores: 1,
+        ..Default::default()
+    };
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        let name = field
+            .content_disposition()
+            .get_name()
+            .unwrap_or_default()
+            .to_string();
+        match name.as_ref() {
+            "target" | "corpus" => {
+                let file = format!("{}.zip", name);
+                let upload = use_upload(field, &job_dir.join(&file), created_by, db_pool).await;
+                if let Some(hash) = field_result(errors, &name, upload)? {
+                    if name == "target" {
+                        job_info.target = file.clone();
+                    } else {
+                        job_info.corpus = file.clone();
+                    }
+                    job_info.artifacts.insert(file, hash);
+                }
+            }
+            _ => {
+                let value = match field_result(errors, &name, read_text(&mut field).await)? {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let applied =
+                    apply_field(&mut job_info, &name, value.trim(), job_dir, db_pool).await;
+                field_result(errors, &name, applied)?;
+            }
+        }
+    }
+    Ok(job_info)
+}
 
-    fs::create_dir_all(&data_dir)?;
+/// Prepares the directory of the new job in TMP_DIR, it is moved to the NFS
+/// once the job is valid
+fn create_job_dir(guid: &str) -> io::Result<PathBuf> {
+    let job_tmp_dir = Path::new(&CONFIG.tmp_dir).join(guid);
+    fs::create_dir_all(job_tmp_dir.join("data"))?;
     fs::create_dir_all(job_tmp_dir.join("res"))?;
     fs::create_dir_all(job_tmp_dir.join("crashes"))?;
+    Ok(job_tmp_dir)
+}
 
-    match process_job_create(&mut payload, &data_dir).await {
-        Ok(_job_info) => {
-            job_info = _job_info;
-            job_info.guid = guid;
+/// Puts the job files which don't come from an artifact into the store, so
+/// the job refers to both its target and its corpus by their hashes
+async fn store_job_files(
+    job_info: &mut JobInfo,
+    data_dir: &Path,
+    db_pool: &SqlitePool,
+) -> Result<(), Error> {
+    for file in [job_info.target.clone(), job_info.corpus.clone()] {
+        if job_info.artifacts.contains_key(&file) {
+            continue;
         }
-        Err(err) => {
-            fs::remove_dir_all(&job_tmp_dir)?;
-            return Err(err);
+
+        let path = data_dir.join(&file);
+        let (hash, size) = web::block(move || store_file(&path)).await??;
+        let size = i64::try_from(size).unwrap_or(0);
+        if let Err(err) = Artifact::store(&hash, size, &job_info.created_by, db_pool).await {
+            error!("Error storing artifact: {}", err);
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Error storing artifact",
+            ));
         }
+        job_info.artifacts.insert(file, hash);
     }
 
-    match sanitize_job_info(&job_info) {
-        Ok(_) => {}
-        Err(err) => {
-            fs::remove_dir_all(&job_tmp_dir)?;
-            return Err(err);
-        }
+    Ok(())
+}
+
+/// Moves the prepared job directory to the NFS and schedules the job
+async fn submit_job(
+    job_info: &mut JobInfo,
+    job_tmp_dir: &Path,
+    db_pool: &SqlitePool,
+    tx: &Arc<Sender<Event>>,
+) -> Result<(), Error> {
+    if let Err(err) = store_job_files(job_info, &job_tmp_dir.join("data"), db_pool).await {
+        fs::remove_dir_all(job_tmp_dir)?;
+        return Err(err);
     }
 
-    let scheduled_jobs = match Job::schedule_job(&job_info, db_pool.get_ref()).await {
+    // The job directory has to be in place before the queued job can be
+    // picked up by the scheduler
+    let job_nfs_dir = get_job_dir(&job_info.guid);
+    fs::rename(job_tmp_dir, &job_nfs_dir)?;
+
+    let scheduled_jobs = match Job::schedule_job(job_info, db_pool).await {
         Ok(res) => res,
         Err(err) => {
-            fs::remove_dir_all(&job_tmp_dir)?;
+            fs::remove_dir_all(&job_nfs_dir)?;
             return Err(actix_web::error::ErrorBadRequest(err));
         }
     };
 
-    fs::rename(job_tmp_dir, job_nfs_dir)?;
-
-    let tx = tx.into_inner();
     for job in scheduled_jobs {
         notify_processor(
-            &tx,
+            tx,
             Event::AgentRequest {
                 guid: job.agent_guid,
                 request: Box::new(Request::JobCreate { job: job.request }),
@@ -168,6 +413,52 @@ async fn create_job(
         .await;
     }
 
+    Ok(())
+}
+
+#[post("/job")]
+async fn create_job(
+    user: User,
+    mut payload: Multipart,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> Result<HttpResponse, Error> {
+    let guid = Uuid::new_v4().to_string();
+    let job_tmp_dir = create_job_dir(&guid)?;
+    let data_dir = job_tmp_dir.join("data/");
+
+    let mut errors = SpecErrors::default();
+    let parsed = process_job_create(
+        &mut payload,
+        &data_dir,
+        &user.username,
+        &mut errors,
+        db_pool.get_ref(),
+    )
+    .await;
+    let mut job_info = match parsed {
+        Ok(job_info) => job_info,
+        Err(err) => {
+            fs::remove_dir_all(&job_tmp_dir)?;
+            return Err(err);
+        }
+    };
+    job_info.guid = guid;
+    job_info.created_by = user.username;
+
+    if let Err(err) = check_job(&job_info, &data_dir, errors, db_pool.get_ref()).await {
+        fs::remove_dir_all(&job_tmp_dir)?;
+        return Err(err);
+    }
+
+    submit_job(
+        &mut job_info,
+        &job_tmp_dir,
+        db_pool.get_ref(),
+        &tx.into_inner(),
+    )
+    .await?;
+
     info!("Created job: {:?}", job_info);
 
     Ok(HttpResponse::Ok().body(job_info.guid))
@@ -209,6 +500,147 @@ async fn get_job_crashes(
     }
 }
 
+/// Streams the findings of the job as a zip usable as corpus.zip
+#[get("/job/{guid}/corpus")]
+async fn get_job_corpus(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<NamedFile, Error> {
+    let guid = guid.into_inner();
+    if let Err(err) = Job::get_job(&guid, db_pool.get_ref()).await {
+        error!("Error fetching job: {}", err);
+        return Err(actix_web::error::ErrorNotFound("Job not found"));
+    }
+
+    let path = Path::new(&CONFIG.tmp_dir).join(format!("{}.zip", Uuid::new_v4()));
+    let zip_path = path.clone();
+    let zip_guid = guid.clone();
+    let zipped = web::block(move || zip_findings(&zip_guid, &zip_path)).await?;
+
+    // The response keeps streaming from the open file once it is unlinked
+    let file = fs::File::open(&path);
+    _ = fs::remove_file(&path);
+    match zipped {
+        Ok(0) => Err(actix_web::error::ErrorNotFound("Job has no findings")),
+        Ok(_) => Ok(NamedFile::from_file(file?, format!("{}-corpus.zip", guid))?),
+        Err(err) => {
+            error!("Error packing findings of {}: {}", guid, err);
+            Err(actix_web::error::ErrorInternalServerError(
+                "Error packing findings",
+            ))
+        }
+    }
+}
+
+/// Stores the findings of the job as a named corpus, optionally minimized
+/// with afl-cmin on an agent first
+#[post("/job/{guid}/corpus")]
+async fn save_job_corpus(
+    user: User,
+    guid: web::Path<String>,
+    req: web::Json<CorpusSaveRequest>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let guid = guid.into_inner();
+    let req = req.into_inner();
+
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("you haven't specified the corpus name");
+    }
+
+    let job = match Job::get_job(&guid, db_pool.get_ref()).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return HttpResponse::NotFound().body("Job not found");
+        }
+    };
+
+    match Corpus::find(req.name.trim(), db_pool.get_ref()).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().body("Corpus with this name already exists")
+        }
+        Err(err) => {
+            error!("Error fetching corpus: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching corpus");
+        }
+    }
+
+    let mut corpus = Corpus {
+        guid: Uuid::new_v4().to_string(),
+        name: req.name.trim().to_string(),
+        description: req.description,
+        creation_date: chrono::offset::Utc::now().to_string(),
+        created_by: user.username,
+        source_job: Some(guid.clone()),
+        status: "ready".to_string(),
+        ..Default::default()
+    };
+
+    if req.minimize {
+        let agent_guid = match Agent::pick_for_job(&guid, db_pool.get_ref()).await {
+            Ok(Some(agent_guid)) => agent_guid,
+            Ok(None) => {
+                return HttpResponse::ServiceUnavailable()
+                    .body("No agent is available for minimization")
+            }
+            Err(err) => {
+                error!("Error picking an agent: {}", err);
+                return HttpResponse::InternalServerError().body("Error picking an agent");
+            }
+        };
+
+        corpus.status = "minimizing".to_string();
+        corpus.last_msg = "Minimization has been requested".to_string();
+        if let Err(err) = Corpus::create(&corpus, db_pool.get_ref()).await {
+            error!("Error creating corpus: {}", err);
+            return HttpResponse::InternalServerError().body("Error creating corpus");
+        }
+
+        let (cpus, ram) = job.task_limits();
+        notify_processor(
+            &tx.into_inner(),
+            Event::AgentRequest {
+                guid: agent_guid,
+                request: Box::new(Request::CorpusMinimize {
+                    request: CorpusMinimizeRequest {
+                        job_guid: guid,
+                        corpus_guid: corpus.guid.clone(),
+                        image: job.job_collection.image,
+                        cpus,
+                        ram,
+                        fuzzer: Some((&job.job_collection.fuzzer).into()),
+                        env: job.job_collection.env.into_iter().collect(),
+                    },
+                }),
+            },
+        )
+        .await;
+
+        return HttpResponse::Accepted().json(corpus);
+    }
+
+    match store_findings(&guid, &corpus.guid) {
+        Ok(Some(size)) => corpus.size = i64::try_from(size).unwrap_or(0),
+        Ok(None) => return HttpResponse::BadRequest().body("Job has no findings"),
+        Err(err) => {
+            error!("Error storing findings of {}: {}", guid, err);
+            return HttpResponse::InternalServerError().body("Error storing findings");
+        }
+    }
+
+    if let Err(err) = Corpus::create(&corpus, db_pool.get_ref()).await {
+        _ = remove_corpus(&corpus.guid);
+        error!("Error creating corpus: {}", err);
+        return HttpResponse::InternalServerError().body("Error creating corpus");
+    }
+
+    info!("Stored findings of {} as corpus {}", guid, corpus.name);
+    HttpResponse::Ok().json(corpus)
+}
+
 #[get("/jobs")]
 async fn get_jobs(db_pool: web::Data<SqlitePool>) -> impl Responder {
     match Job::get_all_collections(db_pool.get_ref()).await {
@@ -230,6 +662,20 @@ async fn stop_job(
     let guid = guid.into_inner();
 
     match Job::get_job(&guid, db_pool.get_ref()).await {
+        Ok(job) if job.job_collection.status == "queued" => {
+            match Job::cancel_queued(&guid, db_pool.get_ref()).await {
+                Ok(true) => {
+                    // The job might have been holding the queue back
+                    notify_processor(&tx, Event::ScheduleQueued).await;
+                    HttpResponse::Ok().body("Job has been removed from the queue")
+                }
+                Ok(false) => HttpResponse::Conflict().body("Job has already left the queue"),
+                Err(err) => {
+                    error!("Error cancelling job: {}", err);
+                    HttpResponse::InternalServerError().body("Error cancelling job")
+                }
+            }
+        }
         Ok(job) => {
             for agent_guid in job.jobs.into_iter().map(|x| x.agent_guid) {
                 notify_processor(
@@ -250,3 +696,430 @@ async fn stop_job(
         }
     }
 }
+
+#[delete("/job/{guid}")]
+async fn delete_job(
+    guid: web::Path<String>,
+    query: web::Query<JobDeleteQuery>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let guid = guid.into_inner();
+
+    let job = match Job::get_job(&guid, db_pool.get_ref()).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching job");
+        }
+    };
+
+    let running = job
+        .jobs
+        .iter()
+        .any(|job| job.status == "init" || job.status == "alive" || job.status == "paused");
+    if running && !query.force {
+        return HttpResponse::Conflict().body("Job is still running, stop it or use force");
+    }
+
+    match purge_job(
+        &guid,
+        query.keep_crashes,
+        db_pool.get_ref(),
+        &tx.into_inner(),
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::Ok().body(guid),
+        Err(err) => {
+            error!("Error deleting job: {}", err);
+            HttpResponse::InternalServerError().body("Error deleting job")
+        }
+    }
+}
+
+async fn notify_sub_jobs(
+    tx: &Arc<Sender<Event>>,
+    agent_guids: Vec<String>,
+    request: impl Fn() -> Request,
+) {
+    for agent_guid in agent_guids {
+        notify_processor(
+            tx,
+            Event::AgentRequest {
+                guid: agent_guid,
+                request: Box::new(request()),
+            },
+        )
+        .await;
+    }
+}
+
+#[post("/job/{guid}/pause")]
+async fn pause_job(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let guid = guid.into_inner();
+
+    match Job::get_job(&guid, db_pool.get_ref()).await {
+        Ok(job) => {
+            let agent_guids = job
+                .jobs
+                .into_iter()
+                .filter(|x| x.status == "alive")
+                .map(|x| x.agent_guid)
+                .collect::<Vec<String>>();
+            if agent_guids.is_empty() {
+                return HttpResponse::BadRequest().body("Job has no alive sub-jobs");
+            }
+
+            notify_sub_jobs(&tx.into_inner(), agent_guids, || Request::JobPause {
+                guid: guid.clone(),
+            })
+            .await;
+            HttpResponse::Ok().body("Job pause request sent")
+        }
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching job")
+        }
+    }
+}
+
+#[post("/job/{guid}/resume")]
+async fn resume_job(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let guid = guid.into_inner();
+
+    match Job::reserve_paused(&guid, db_pool.get_ref()).await {
+        Ok(agent_guids) if agent_guids.is_empty() => {
+            HttpResponse::BadRequest().body("Job has no paused sub-jobs")
+        }
+        Ok(agent_guids) => {
+            notify_sub_jobs(&tx.into_inner(), agent_guids, || Request::JobResume {
+                guid: guid.clone(),
+            })
+            .await;
+            HttpResponse::Ok().body("Job resume request sent")
+        }
+        Err(err) => {
+            error!("Error resuming job: {}", err);
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+    }
+}
+
+#[post("/job/{guid}/restart")]
+async fn restart_job(
+    guid: web::Path<String>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let scheduled_jobs = match Job::restart_job(&guid, db_pool.get_ref()).await {
+        Ok(scheduled_jobs) => scheduled_jobs,
+        Err(err) => {
+            error!("Error restarting job: {}", err);
+            return HttpResponse::BadRequest().body(err.to_string());
+        }
+    };
+
+    let tx = tx.into_inner();
+    for job in scheduled_jobs {
+        notify_processor(
+            &tx,
+            Event::AgentRequest {
+                guid: job.agent_guid,
+                request: Box::new(Request::JobCreate { job: job.request }),
+            },
+        )
+        .await;
+    }
+
+    info!("Restarted job: {}", guid);
+
+    HttpResponse::Ok().body(guid.into_inner())
+}
+
+#[post("/job/{guid}/clone")]
+async fn clone_job(
+    user: User,
+    guid: web::Path<String>,
+    req: web::Json<JobCloneRequest>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> Result<HttpResponse, Error> {
+    let mut job_info = match Job::get_job_info(&guid, db_pool.get_ref()).await {
+        Ok(job_info) => job_info,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return Err(actix_web::error::ErrorNotFound("Job not found"));
+        }
+    };
+
+    let req = req.into_inner();
+    job_info.guid = Uuid::new_v4().to_string();
+    job_info.name = req.name.unwrap_or(format!("{} (clone)", job_info.name));
+    job_info.cpus = req.cpus.unwrap_or(job_info.cpus);
+    job_info.ram = req.ram.unwrap_or(job_info.ram);
+    job_info.timeout = req.timeout.unwrap_or(job_info.timeout);
+    job_info.created_by = user.username;
+
+    // Only the target and the corpus are taken, the findings stay with the
+    // original job. They are linked along with the artifacts they come from
+    let src_data_dir = Path::new(&CONFIG.nfs_dir)
+        .join("jobs")
+        .join(guid.as_str())
+        .join("data");
+    let job_tmp_dir = create_job_dir(&job_info.guid)?;
+    let data_dir = job_tmp_dir.join("data/");
+
+    for file in [&job_info.target, &job_info.corpus] {
+        if let Err(err) = link_or_copy(&src_data_dir.join(file), &data_dir.join(file)) {
+            fs::remove_dir_all(&job_tmp_dir)?;
+            return Err(err.into());
+        }
+    }
+
+    if let Err(err) = check_job(
+        &job_info,
+        &data_dir,
+        SpecErrors::default(),
+        db_pool.get_ref(),
+    )
+    .await
+    {
+        fs::remove_dir_all(&job_tmp_dir)?;
+        return Err(err);
+    }
+
+    submit_job(
+        &mut job_info,
+        &job_tmp_dir,
+        db_pool.get_ref(),
+        &tx.into_inner(),
+    )
+    .await?;
+
+    info!("Cloned job {} into {:?}", guid, job_info);
+
+    Ok(HttpResponse::Ok().body(job_info.guid))
+}
+
+/// Links the target and the corpus referred to by the spec into the job
+async fn use_spec_files(
+    spec: &JobSpec,
+    job_info: &mut JobInfo,
+    job_dir: &Path,
+    errors: &mut SpecErrors,
+    db_pool: &SqlitePool,
+) -> Result<(), Error> {
+    let hash = &spec.target.artifact;
+    let target = use_artifact(hash, &job_dir.join("target.zip"), db_pool).await;
+    if field_result(errors, "target", target)?.is_some() {
+        job_info.target = "target.zip".to_string();
+        job_info
+            .artifacts
+            .insert(job_info.target.clone(), hash.to_string());
+    }
+
+    let corpus = match &spec.corpus {
+        CorpusRef::Artifact(hash) => use_artifact(hash, &job_dir.join("corpus.zip"), db_pool)
+            .await
+            .map(|_| Some(hash.to_string())),
+        CorpusRef::Name(reference) => use_stored_corpus(reference, job_dir, db_pool)
+            .await
+            .map(|_| None),
+        CorpusRef::Job(job_guid) => use_findings(job_guid, job_dir).map(|_| None),
+    };
+    if let Some(hash) = field_result(errors, "corpus", corpus)? {
+        job_info.corpus = "corpus.zip".to_string();
+        if let Some(hash) = hash {
+            job_info.artifacts.insert(job_info.corpus.clone(), hash);
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the job from a JSON or a YAML spec, the format is taken from the
+/// content type
+#[post("/jobs/spec")]
+async fn create_job_from_spec(
+    user: User,
+    req: HttpRequest,
+    body: web::Bytes,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> Result<HttpResponse, Error> {
+    let yaml = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| content_type.contains("yaml"));
+    let parsed = if yaml {
+        serde_yaml::from_slice::<JobSpec>(&body).map_err(|err| err.to_string())
+    } else {
+        serde_json::from_slice::<JobSpec>(&body).map_err(|err| err.to_string())
+    };
+    let spec = match parsed {
+        Ok(spec) => spec,
+        Err(err) => {
+            let mut errors = SpecErrors::default();
+            errors.add("spec", err);
+            return Err(errors.into());
+        }
+    };
+
+    let guid = Uuid::new_v4().to_string();
+    let job_tmp_dir = create_job_dir(&guid)?;
+    let data_dir = job_tmp_dir.join("data/");
+
+    let mut job_info = spec.job_info();
+    job_info.guid = guid;
+    job_info.created_by = user.username;
+
+    let mut errors = SpecErrors::default();
+    let linked = use_spec_files(
+        &spec,
+        &mut job_info,
+        &data_dir,
+        &mut errors,
+        db_pool.get_ref(),
+    )
+    .await;
+    if let Err(err) = linked {
+        fs::remove_dir_all(&job_tmp_dir)?;
+        return Err(err);
+    }
+
+    if let Err(err) = check_job(&job_info, &data_dir, errors, db_pool.get_ref()).await {
+        fs::remove_dir_all(&job_tmp_dir)?;
+        return Err(err);
+    }
+
+    submit_job(
+        &mut job_info,
+        &job_tmp_dir,
+        db_pool.get_ref(),
+        &tx.into_inner(),
+    )
+    .await?;
+
+    info!("Created job from spec: {:?}", job_info);
+
+    Ok(HttpResponse::Ok().body(job_info.guid))
+}
+
+#[get("/jobs/spec/schema")]
+async fn get_job_spec_schema() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/schema+json")
+        .body(JOB_SPEC_SCHEMA)
+}
+
+/// Exports the job as a spec accepted by /jobs/spec, JSON unless YAML is
+/// asked for with ?format=yaml
+#[get("/job/{guid}/spec")]
+async fn get_job_spec(
+    guid: web::Path<String>,
+    query: web::Query<JobSpecQuery>,
+    db_pool: web::Data<SqlitePool>,
+) -> Result<HttpResponse, Error> {
+    let job_info = match Job::get_job_info(&guid, db_pool.get_ref()).await {
+        Ok(job_info) => job_info,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return Err(actix_web::error::ErrorNotFound("Job not found"));
+        }
+    };
+
+    let artifact = |file: &str| job_info.artifacts.get(file).cloned();
+    let (target, corpus) = match (artifact(&job_info.target), artifact(&job_info.corpus)) {
+        (Some(target), Some(corpus)) => (target, corpus),
+        _ => {
+            return Err(actix_web::error::ErrorConflict(
+                "The job has no stored artifacts to refer to",
+            ))
+        }
+    };
+    let spec = JobSpec::from_job_info(job_info, target, corpus);
+
+    if query.format.as_deref() == Some("yaml") {
+        let body =
+            serde_yaml::to_string(&spec).map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(HttpResponse::Ok()
+            .content_type("application/yaml")
+            .body(body))
+    } else {
+        Ok(HttpResponse::Ok().json(spec))
+    }
+}
+
+#[get("/job/{guid}/log/stream")]
+async fn stream_job_log(
+    guid: web::Path<String>,
+    query: web::Query<LogStreamQuery>,
+    db_pool: web::Data<SqlitePool>,
+    tx: web::Data<Sender<Event>>,
+) -> impl Responder {
+    let guid = guid.into_inner();
+
+    let job = match Job::get_job(&guid, db_pool.get_ref()).await {
+        Ok(job) => job,
+        Err(err) => {
+            error!("Error fetching job: {}", err);
+            return HttpResponse::InternalServerError().body("Error fetching job");
+        }
+    };
+
+    let sub_job = match job.jobs.into_iter().find(|x| x.idx == query.idx) {
+        Some(sub_job) => sub_job,
+        None => return HttpResponse::NotFound().body("Sub-job not found"),
+    };
+    // Only the containers of the running sub-jobs have a log to follow
+    if !matches!(sub_job.status.as_str(), "init" | "alive" | "paused") {
+        return HttpResponse::Conflict().body("Sub-job isn't running");
+    }
+
+    let (sender, receiver) = mpsc::channel::<Result<String, String>>(100);
+    notify_processor(
+        &tx.into_inner(),
+        Event::AgentRequest {
+            guid: sub_job.agent_guid,
+            request: Box::new(Request::JobLogs {
+                guid,
+                idx: sub_job.idx,
+                sender,
+            }),
+        },
+    )
+    .await;
+
+    // Every chunk is sent as a Server-Sent Event, multiline chunks are split
+    // into several data fields of the same event. An upstream error is sent
+    // as a log-error event right before the stream is closed
+    let events = stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        let (name, chunk, receiver) = match receiver.recv().await? {
+            Ok(chunk) => ("message", chunk, Some(receiver)),
+            Err(err) => ("log-error", err, None),
+        };
+        let event = format!("event: {}\n", name)
+            + &chunk
+                .lines()
+                .map(|line| format!("data: {}\n", line))
+                .collect::<String>()
+            + "\n";
+        Some((Ok::<_, Error>(web::Bytes::from(event)), receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
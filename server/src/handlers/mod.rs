@@ -1,5 +1,9 @@
 pub mod agent;
+pub mod artifact;
+pub mod bucket;
+pub mod corpus;
 pub mod crash;
 pub mod job;
 pub mod stats;
+pub mod user;
 pub mod web;
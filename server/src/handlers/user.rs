@@ -0,0 +1,113 @@
+use crate::auth::{request_token, SESSION_COOKIE};
+use crate::models::{LoginRequest, TokenCreateRequest, TokenResponse, User, UserCreateRequest};
+
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    delete, get, post, web, HttpRequest, HttpResponse, Responder,
+};
+use log::error;
+use sqlx::SqlitePool;
+
+#[post("/login")]
+async fn login(req: web::Json<LoginRequest>, db_pool: web::Data<SqlitePool>) -> impl Responder {
+    let user = match User::authenticate(&req, db_pool.get_ref()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid username or password"),
+        Err(err) => {
+            error!("Failed to authenticate {}: {}", req.username, err);
+            return HttpResponse::InternalServerError().body("Failed to authenticate");
+        }
+    };
+
+    match user.issue_token("session", "", db_pool.get_ref()).await {
+        Ok(token) => HttpResponse::Ok()
+            .cookie(
+                Cookie::build(SESSION_COOKIE, token)
+                    .path("/")
+                    .http_only(true)
+                    .same_site(SameSite::Strict)
+                    .finish(),
+            )
+            .json(user),
+        Err(err) => {
+            error!("Failed to create session for {}: {}", user.username, err);
+            HttpResponse::InternalServerError().body("Failed to create session")
+        }
+    }
+}
+
+#[post("/logout")]
+async fn logout(req: HttpRequest, db_pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Some(token) = request_token(&req) {
+        if let Err(err) = User::revoke_token(&token, db_pool.get_ref()).await {
+            error!("Failed to revoke session: {}", err);
+        }
+    }
+
+    let mut cookie = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    HttpResponse::Ok().cookie(cookie).body("Logged out")
+}
+
+#[get("/me")]
+async fn whoami(user: User) -> impl Responder {
+    user
+}
+
+#[post("/token")]
+async fn create_token(
+    user: User,
+    req: web::Json<TokenCreateRequest>,
+    db_pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    match user
+        .issue_token("api", &req.description, db_pool.get_ref())
+        .await
+    {
+        Ok(token) => HttpResponse::Ok().json(TokenResponse { token }),
+        Err(err) => {
+            error!("Failed to create token for {}: {}", user.username, err);
+            HttpResponse::InternalServerError().body("Failed to create token")
+        }
+    }
+}
+
+#[get("/users")]
+async fn get_users(db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match User::get_all(db_pool.get_ref()).await {
+        Ok(users) => HttpResponse::Ok().json(users),
+        Err(err) => {
+            error!("Error fetching users: {}", err);
+            HttpResponse::InternalServerError().body("Error fetching users")
+        }
+    }
+}
+
+#[post("/user")]
+async fn create_user(
+    req: web::Json<UserCreateRequest>,
+    db_pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if req.username.is_empty() || req.password.is_empty() {
+        return HttpResponse::BadRequest().body("Username and password are required");
+    }
+
+    match User::create(&req, db_pool.get_ref()).await {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(err) => {
+            error!("Error creating user: {}", err);
+            HttpResponse::InternalServerError().body("Error creating user")
+        }
+    }
+}
+
+#[delete("/user/{guid}")]
+async fn delete_user(guid: web::Path<String>, db_pool: web::Data<SqlitePool>) -> impl Responder {
+    match User::delete(guid.into_inner(), db_pool.get_ref()).await {
+        Ok(guid) => HttpResponse::Ok().body(format!("Succesfully deleted {} user", guid)),
+        Err(err) => {
+            error!("Error deleting user: {}", err);
+            HttpResponse::InternalServerError().body("Error deleting user")
+        }
+    }
+}
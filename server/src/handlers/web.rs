@@ -70,3 +70,27 @@ async fn crash() -> HttpResponse {
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
+
+#[get("/buckets")]
+async fn buckets() -> HttpResponse {
+    match TEMPLATES.render("buckets.html", &tera::Context::new()) {
+        Ok(t) => HttpResponse::Ok().content_type("text/html").body(t),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/bucket/{id}")]
+async fn bucket() -> HttpResponse {
+    match TEMPLATES.render("bucket_page.html", &tera::Context::new()) {
+        Ok(t) => HttpResponse::Ok().content_type("text/html").body(t),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/login")]
+async fn login_page() -> HttpResponse {
+    match TEMPLATES.render("login.html", &tera::Context::new()) {
+        Ok(t) => HttpResponse::Ok().content_type("text/html").body(t),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
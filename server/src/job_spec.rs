@@ -0,0 +1,526 @@
+use std::{collections::BTreeMap, fmt, fs, io::Read, path::Path};
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use zip::ZipArchive;
+
+use crate::handlers::agent::JobInfo;
+use crate::models::{Agent, FuzzerOptions, Labels, FUZZER_MODES, POWER_SCHEDULES};
+use crate::scheduler::{get_scheduler, DEFAULT_STRATEGY};
+
+/// JSON schema of the job spec, served at /api/jobs/spec/schema
+pub const JOB_SPEC_SCHEMA: &str = include_str!("../job-spec.schema.json");
+
+/// The target is always taken from the artifact store
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetRef {
+    pub artifact: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorpusRef {
+    /// Hash of a stored artifact
+    Artifact(String),
+    /// Name or guid of a stored corpus
+    Name(String),
+    /// Guid of the job to take the findings of
+    Job(String),
+}
+
+/// Declarative job document accepted by /api/jobs/spec in JSON or YAML. The
+/// keys are named as the fields of the job form, so the field errors are
+/// the same for both. The fuzzer options are fuzzer-<key> in the form
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct JobSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub agent_type: String,
+    #[serde(default)]
+    pub image: String,
+    pub cpus: u64,
+    /// RAM in KB, 0 runs the job without a limit
+    #[serde(default)]
+    pub ram: u64,
+    #[serde(default = "default_min_cores")]
+    pub min_cores: u64,
+    #[serde(default)]
+    pub timeout: String,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agents: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub constraints: Labels,
+    #[serde(default)]
+    pub crash_auto_analyze: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub fuzzer: FuzzerOptions,
+    pub target: TargetRef,
+    pub corpus: CorpusRef,
+}
+
+fn default_min_cores() -> u64 {
+    1
+}
+
+fn default_strategy() -> String {
+    DEFAULT_STRATEGY.to_string()
+}
+
+impl JobSpec {
+    /// The job described by the spec, the target and the corpus are put in
+    /// place by the caller
+    pub fn job_info(&self) -> JobInfo {
+        JobInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            agent_type: self.agent_type.clone(),
+            image: self.image.clone(),
+            cpus: self.cpus,
+            ram: self.ram,
+            min_cores: self.min_cores,
+            timeout: self.timeout.clone(),
+            priority: self.priority,
+            strategy: self.strategy.clone(),
+            pinned_agents: self.agents.clone(),
+            constraints: self.constraints.clone(),
+            crash_auto_analyze: self.crash_auto_analyze,
+            env: self.env.clone(),
+            fuzzer: self.fuzzer.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The spec of an existing job, its target and corpus are referred to
+    /// by their artifacts
+    pub fn from_job_info(job_info: JobInfo, target: String, corpus: String) -> JobSpec {
+        JobSpec {
+            name: job_info.name,
+            description: job_info.description,
+            agent_type: job_info.agent_type,
+            image: job_info.image,
+            cpus: job_info.cpus,
+            ram: job_info.ram,
+            min_cores: job_info.min_cores,
+            timeout: job_info.timeout,
+            priority: job_info.priority,
+            strategy: job_info.strategy,
+            agents: job_info.pinned_agents,
+            constraints: job_info.constraints,
+            crash_auto_analyze: job_info.crash_auto_analyze,
+            env: job_info.env,
+            fuzzer: job_info.fuzzer,
+            target: TargetRef { artifact: target },
+            corpus: CorpusRef::Artifact(corpus),
+        }
+    }
+}
+
+/// A field of the job spec which failed the validation
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every failing field of the job spec, returned as the JSON body of the
+/// 400 response
+#[derive(Debug, Default, Serialize)]
+pub struct SpecErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl SpecErrors {
+    /// Only the first error of the field is kept, the later checks of an
+    /// unparsable field would just repeat it
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        if !self.has(field) {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                message: message.into(),
+            });
+        }
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.errors.iter().any(|err| err.field == field)
+    }
+
+    pub fn into_result(self) -> Result<(), SpecErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for SpecErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self
+            .errors
+            .iter()
+            .map(|err| format!("{}: {}", err.field, err.message))
+            .collect::<Vec<String>>();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl ResponseError for SpecErrors {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+/// Checks the docker image reference: [registry[:port]/]name[:tag][@digest]
+pub fn valid_image(image: &str) -> bool {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+
+    if let Some(digest) = digest {
+        let valid = match digest.split_once(':') {
+            Some((algorithm, hex)) => {
+                !algorithm.is_empty()
+                    && algorithm
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+._-".contains(c))
+                    && hex.len() >= 32
+                    && hex.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            None => false,
+        };
+        if !valid {
+            return false;
+        }
+    }
+
+    // The tag follows the last colon which isn't a part of the registry port
+    let (name, tag) = match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (name, None),
+    };
+
+    if let Some(tag) = tag {
+        let valid = tag.len() <= 128
+            && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+        if !valid {
+            return false;
+        }
+    }
+
+    if name.is_empty() || name.len() > 255 {
+        return false;
+    }
+
+    let mut components = name.split('/').collect::<Vec<&str>>();
+    let first = components[0];
+    if components.len() > 1 && (first.contains('.') || first.contains(':') || first == "localhost")
+    {
+        let (host, port) = match first.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (first, None),
+        };
+        if host.is_empty()
+            || !host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+            || port.map_or(false, |port| {
+                port.is_empty() || !port.chars().all(|c| c.is_ascii_digit())
+            })
+        {
+            return false;
+        }
+        components.remove(0);
+    }
+
+    components.iter().all(|component| {
+        !component.is_empty()
+            && component.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && component.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && component
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+    })
+}
+
+fn valid_env_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Name of the target binary in target.zip, schedule.py takes SAP_TEST_BIN
+/// of the LAUNCH section and falls back to target
+fn test_bin(config: &str) -> String {
+    let mut section = "";
+    for line in config.lines().map(str::trim) {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name;
+        } else if section == "LAUNCH" {
+            if let Some((key, value)) = line.split_once(|c| c == '=' || c == ':') {
+                if key.trim() == "SAP_TEST_BIN" && !value.trim().is_empty() {
+                    return value.trim().to_string();
+                }
+            }
+        }
+    }
+    "target".to_string()
+}
+
+fn open_zip(path: &Path) -> Result<ZipArchive<fs::File>, String> {
+    let file = fs::File::open(path).map_err(|err| err.to_string())?;
+    ZipArchive::new(file).map_err(|_| "isn't a valid zip archive".to_string())
+}
+
+fn zip_contains(path: &Path, name: &str) -> bool {
+    let mut archive = match open_zip(path) {
+        Ok(archive) => archive,
+        Err(_) => return false,
+    };
+    let found = archive.by_name(name).is_ok();
+    found
+}
+
+/// Checks target.zip unpacks into what schedule.py expects: config.ini and
+/// the target binary
+fn check_target(path: &Path) -> Result<(), String> {
+    let mut archive = open_zip(path)?;
+
+    let mut config = String::new();
+    match archive.by_name("config.ini") {
+        Ok(mut file) => {
+            file.read_to_string(&mut config)
+                .map_err(|err| format!("config.ini can't be read: {}", err))?;
+        }
+        Err(_) => return Err("doesn't contain config.ini".to_string()),
+    }
+
+    let test_bin = test_bin(&config);
+    if archive.by_name(&test_bin).is_err() {
+        return Err(format!("doesn't contain the target binary {}", test_bin));
+    }
+
+    Ok(())
+}
+
+/// Options of afl-fuzz which are set from the job and can't be passed as
+/// the extra arguments
+const RESERVED_ARGS: &[&str] = &[
+    "-i", "-o", "-M", "-S", "-T", "-Q", "-O", "-U", "-x", "-p", "--",
+];
+
+fn check_fuzzer(fuzzer: &FuzzerOptions, target: Option<&Path>, errors: &mut SpecErrors) {
+    if !FUZZER_MODES.contains(&fuzzer.mode.as_str()) {
+        errors.add(
+            "fuzzer-mode",
+            format!("mode must be one of {}", FUZZER_MODES.join(", ")),
+        );
+    }
+
+    if !fuzzer.dict.is_empty() {
+        let dict = Path::new(&fuzzer.dict);
+        let relative = dict
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+        if !relative {
+            errors.add("fuzzer-dict", "dictionary must be a path inside target.zip");
+        } else if let Some(target) = target {
+            if !zip_contains(target, &fuzzer.dict) {
+                errors.add(
+                    "fuzzer-dict",
+                    format!("target.zip doesn't contain {}", fuzzer.dict),
+                );
+            }
+        }
+    }
+
+    if let Some(arg) = fuzzer
+        .extra_args
+        .iter()
+        .find(|arg| RESERVED_ARGS.contains(&arg.as_str()))
+    {
+        errors.add(
+            "fuzzer-args",
+            format!("{} is set from the job and can't be passed", arg),
+        );
+    }
+
+    if let Some(schedule) = fuzzer
+        .schedules
+        .iter()
+        .find(|schedule| !POWER_SCHEDULES.contains(&schedule.as_str()))
+    {
+        errors.add(
+            "fuzzer-schedules",
+            format!("unknown power schedule {}", schedule),
+        );
+    }
+}
+
+fn check_corpus(path: &Path) -> Result<(), String> {
+    let archive = open_zip(path)?;
+    if archive.is_empty() {
+        return Err("is empty".to_string());
+    }
+    Ok(())
+}
+
+/// Validates the parsed job, the files are checked in the data directory of
+/// the job. Every failure is added to the errors of the parsing
+pub async fn validate_job(
+    job_info: &JobInfo,
+    data_dir: &Path,
+    errors: &mut SpecErrors,
+    pool: &SqlitePool,
+) -> Result<()> {
+    if job_info.agent_type.is_empty() {
+        errors.add("agent-type", "you haven't specified agent type");
+    } else if !Agent::has_agent_type(&job_info.agent_type, pool).await? {
+        errors.add(
+            "agent-type",
+            format!("there are no agents of type {}", job_info.agent_type),
+        );
+    }
+
+    if job_info.agent_type == "linux" && job_info.image.is_empty() {
+        errors.add("image", "you haven't specified image");
+    } else if !job_info.image.is_empty() && !valid_image(&job_info.image) {
+        errors.add("image", "invalid image reference");
+    }
+
+    if job_info.cpus == 0 {
+        errors.add("cpus", "you haven't specified number of cpu cores");
+    }
+
+    if get_scheduler(&job_info.strategy).is_none() {
+        errors.add("strategy", "unknown scheduling strategy");
+    }
+
+    if job_info.strategy == "pin" && job_info.pinned_agents.is_empty() {
+        errors.add("agents", "you haven't specified agents to pin the job to");
+    }
+
+    if job_info.min_cores == 0 || (job_info.cpus > 0 && job_info.min_cores > job_info.cpus) {
+        errors.add(
+            "min-cores",
+            "minimum cores per agent must be between 1 and the number of cpu cores",
+        );
+    }
+
+    if !job_info.timeout.is_empty() && job_info.timeout.parse::<humantime::Duration>().is_err() {
+        errors.add("timeout", "invalid timeout format");
+    }
+
+    if let Some(key) = job_info.env.keys().find(|key| !valid_env_name(key)) {
+        errors.add("env", format!("invalid variable name {}", key));
+    }
+
+    if job_info.target.is_empty() {
+        errors.add("target", "you haven't specified target.zip");
+    } else if let Err(err) = check_target(&data_dir.join(&job_info.target)) {
+        errors.add("target", format!("target.zip {}", err));
+    }
+
+    // The dictionary is looked up only in a valid target.zip
+    let target = (!job_info.target.is_empty() && !errors.has("target"))
+        .then(|| data_dir.join(&job_info.target));
+    check_fuzzer(&job_info.fuzzer, target.as_deref(), errors);
+
+    if job_info.corpus.is_empty() {
+        errors.add(
+            "corpus",
+            "you haven't specified corpus.zip, a stored corpus or a previous job",
+        );
+    } else if let Err(err) = check_corpus(&data_dir.join(&job_info.corpus)) {
+        errors.add("corpus", format!("corpus.zip {}", err));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_accepts_image_references() {
+        for image in [
+            "fuzzbox",
+            "repo/fuzzbox:latest",
+            "registry.local:5000/team/fuzzbox:v1.2",
+            "localhost/fuzzbox",
+            "fuzzbox@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ] {
+            assert!(valid_image(image), "{}", image);
+        }
+    }
+
+    #[test]
+    fn it_rejects_invalid_image_references() {
+        for image in [
+            "",
+            "Repo/fuzzbox",
+            "repo//fuzzbox",
+            "repo/fuzzbox:",
+            "repo/fuzzbox:-tag",
+            "registry.local:port/fuzzbox",
+            "fuzzbox@sha256:xyz",
+            "repo/fuzzbox latest",
+        ] {
+            assert!(!valid_image(image), "{}", image);
+        }
+    }
+
+    #[test]
+    fn it_finds_the_test_binary() {
+        assert_eq!(test_bin(""), "target");
+        assert_eq!(test_bin("[LAUNCH]\nSAP_TEST_BIN = bin/app\n"), "bin/app");
+        assert_eq!(test_bin("[OTHER]\nSAP_TEST_BIN = app\n"), "target");
+    }
+
+    #[test]
+    fn it_checks_the_fuzzer_options() {
+        let mut errors = SpecErrors::default();
+        check_fuzzer(&FuzzerOptions::default(), None, &mut errors);
+        assert!(errors.errors.is_empty());
+
+        let fuzzer = FuzzerOptions {
+            mode: "afl".to_string(),
+            dict: "../dict".to_string(),
+            extra_args: vec!["-t".to_string(), "1000+".to_string(), "-M".to_string()],
+            schedules: vec!["fast".to_string(), "slow".to_string()],
+            ..Default::default()
+        };
+        let mut errors = SpecErrors::default();
+        check_fuzzer(&fuzzer, None, &mut errors);
+        for field in [
+            "fuzzer-mode",
+            "fuzzer-dict",
+            "fuzzer-args",
+            "fuzzer-schedules",
+        ] {
+            assert!(errors.has(field), "{}", field);
+        }
+    }
+}
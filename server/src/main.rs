@@ -1,11 +1,17 @@
 use crate::server::server;
 
+mod artifact;
+mod auth;
 mod broker;
 mod config;
+mod corpus;
 mod handlers;
+mod job_spec;
 mod models;
 mod protos;
+mod retention;
 mod routes;
+mod scheduler;
 mod server;
 mod utils;
 
@@ -1,10 +1,67 @@
-use crate::protos::agent::SysInfo;
+use std::collections::{BTreeMap, HashMap};
+
+use super::user::{hash_token, new_token};
+use crate::protos::agent::{JobCreateRequest, SysInfo};
 
 use actix_http::body::BoxBody;
 use actix_web::{HttpRequest, HttpResponse, Responder};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::{Executor, Sqlite, SqlitePool, Transaction};
+
+/// Free-form key/value labels of an agent, e.g. arch=arm64 or pool=nightly
+pub type Labels = BTreeMap<String, String>;
+
+/// Parses the comma separated "key=value" pairs used for the labels and the
+/// placement constraints of the jobs
+pub fn parse_labels(labels: &str) -> Result<Labels> {
+    let mut parsed = Labels::new();
+    for label in labels
+        .split(',')
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+    {
+        match label.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                parsed.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "invalid label {}, expected key=value",
+                    label
+                ))
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+pub fn format_labels(labels: &Labels) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Checks the labels can be stored and parsed back
+pub fn validate_labels(labels: &Labels) -> Result<()> {
+    for (key, value) in labels {
+        if key.is_empty() || key.contains(['=', ',']) || value.contains(',') {
+            return Err(anyhow::anyhow!("invalid label {}={}", key, value));
+        }
+    }
+    Ok(())
+}
+
+/// Agents not reporting the overcommit don't overcommit
+fn overcommit(ratio: f64) -> f64 {
+    if ratio > 0.0 {
+        ratio
+    } else {
+        1.0
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct AgentRequest {
@@ -16,6 +73,47 @@ pub struct AgentCreateRequest {
     pub description: String,
     pub agent_type: String,
     pub endpoint: String,
+    #[serde(default)]
+    pub labels: Labels,
+}
+
+/// Sent by an agent registering itself with the join token
+#[derive(Serialize, Deserialize)]
+pub struct AgentRegisterRequest {
+    pub guid: Option<String>,
+    /// Returned by the first registration, proves the agent owns the guid
+    pub secret: Option<String>,
+    pub description: String,
+    pub agent_type: String,
+    pub endpoint: String,
+    pub cpus: u64,
+    pub ram: u64,
+    #[serde(default)]
+    pub reserved_cpus: u64,
+    #[serde(default)]
+    pub reserved_ram: u64,
+    #[serde(default)]
+    pub cpu_overcommit: f64,
+    #[serde(default)]
+    pub ram_overcommit: f64,
+    #[serde(default)]
+    pub labels: Labels,
+    pub version: String,
+}
+
+/// The secret is only sent to a newly registered agent
+#[derive(Serialize, Deserialize)]
+pub struct AgentRegisterResponse {
+    pub guid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+pub const AGENT_STATES: &[&str] = &["active", "draining", "maintenance"];
+
+#[derive(Serialize, Deserialize)]
+pub struct AgentStateRequest {
+    pub state: String,
 }
 
 impl Responder for AgentCreateRequest {
@@ -26,7 +124,7 @@ impl Responder for AgentCreateRequest {
     }
 }
 
-#[derive(Serialize, Deserialize, FromRow, Default)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Agent {
     pub guid: String,
     pub description: String,
@@ -37,6 +135,26 @@ pub struct Agent {
     pub free_ram: Option<i64>,
     pub cpus: Option<i64>,
     pub ram: Option<i64>,
+    pub status_changed: Option<String>,
+    pub last_seen: Option<String>,
+    pub created_by: String,
+    pub version: Option<String>,
+    /// active, draining or maintenance, only the active agents get new jobs
+    pub state: String,
+    pub labels: Labels,
+    pub load_avg: Option<f64>,
+    pub available_ram: Option<i64>,
+    pub disk_total: Option<i64>,
+    pub disk_free: Option<i64>,
+    /// Mismatches found by the last reconciliation, None if there were none
+    pub drift: Option<String>,
+    pub reconciled_at: Option<String>,
+    /// Cores and RAM the agent keeps for the host, cpus and ram are what it
+    /// offers after the reservation and the overcommit
+    pub reserved_cpus: i64,
+    pub reserved_ram: i64,
+    pub cpu_overcommit: f64,
+    pub ram_overcommit: f64,
 }
 
 impl Responder for Agent {
@@ -49,28 +167,47 @@ impl Responder for Agent {
 
 impl Agent {
     pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Agent>> {
-        let agents = sqlx::query!(
+        let rec = sqlx::query!(
             r#"
-            SELECT guid, description, agent_type, endpoint, status, free_cpus, free_ram, cpus, ram
+            SELECT guid, description, agent_type, endpoint, status, free_cpus, free_ram, cpus, ram, status_changed, last_seen, created_by, version, state, load_avg as "load_avg: f64", available_ram, disk_total, disk_free, drift, reconciled_at, reserved_cpus, reserved_ram, cpu_overcommit as "cpu_overcommit: f64", ram_overcommit as "ram_overcommit: f64"
             FROM agents
             ORDER BY guid
             "#
         )
         .fetch_all(pool)
-        .await?
-        .into_iter()
-        .map(|rec| Agent {
-            guid: rec.guid,
-            description: rec.description,
-            agent_type: rec.agent_type,
-            endpoint: rec.endpoint,
-            status: rec.status,
-            free_cpus: rec.free_cpus,
-            free_ram: rec.free_ram,
-            cpus: rec.cpus,
-            ram: rec.ram,
-        })
-        .collect();
+        .await?;
+
+        let mut labels = Self::get_all_labels(pool).await?;
+        let agents = rec
+            .into_iter()
+            .map(|rec| Agent {
+                labels: labels.remove(&rec.guid).unwrap_or_default(),
+                guid: rec.guid,
+                description: rec.description,
+                agent_type: rec.agent_type,
+                endpoint: rec.endpoint,
+                status: rec.status,
+                free_cpus: rec.free_cpus,
+                free_ram: rec.free_ram,
+                cpus: rec.cpus,
+                ram: rec.ram,
+                status_changed: rec.status_changed,
+                last_seen: rec.last_seen,
+                created_by: rec.created_by,
+                version: rec.version,
+                state: rec.state,
+                load_avg: rec.load_avg,
+                available_ram: rec.available_ram,
+                disk_total: rec.disk_total,
+                disk_free: rec.disk_free,
+                drift: rec.drift,
+                reconciled_at: rec.reconciled_at,
+                reserved_cpus: rec.reserved_cpus,
+                reserved_ram: rec.reserved_ram,
+                cpu_overcommit: rec.cpu_overcommit,
+                ram_overcommit: rec.ram_overcommit,
+            })
+            .collect();
 
         Ok(agents)
     }
@@ -78,7 +215,7 @@ impl Agent {
     pub async fn get_by_guid(guid: &str, pool: &SqlitePool) -> Result<Option<Agent>> {
         let rec = sqlx::query!(
             r#"
-            SELECT guid, description, agent_type, endpoint, status, free_cpus, free_ram, cpus, ram
+            SELECT guid, description, agent_type, endpoint, status, free_cpus, free_ram, cpus, ram, status_changed, last_seen, created_by, version, state, load_avg as "load_avg: f64", available_ram, disk_total, disk_free, drift, reconciled_at, reserved_cpus, reserved_ram, cpu_overcommit as "cpu_overcommit: f64", ram_overcommit as "ram_overcommit: f64"
             FROM agents
             WHERE guid = $1
             "#,
@@ -87,7 +224,16 @@ impl Agent {
         .fetch_optional(&*pool)
         .await?;
 
-        Ok(rec.map(|rec| Agent {
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return Ok(None),
+        };
+        let labels = Self::get_all_labels(pool)
+            .await?
+            .remove(&rec.guid)
+            .unwrap_or_default();
+
+        Ok(Some(Agent {
             guid: rec.guid,
             description: rec.description,
             agent_type: rec.agent_type,
@@ -97,31 +243,273 @@ impl Agent {
             free_ram: rec.free_ram,
             cpus: rec.cpus,
             ram: rec.ram,
+            status_changed: rec.status_changed,
+            last_seen: rec.last_seen,
+            created_by: rec.created_by,
+            version: rec.version,
+            state: rec.state,
+            load_avg: rec.load_avg,
+            available_ram: rec.available_ram,
+            disk_total: rec.disk_total,
+            disk_free: rec.disk_free,
+            drift: rec.drift,
+            reconciled_at: rec.reconciled_at,
+            reserved_cpus: rec.reserved_cpus,
+            reserved_ram: rec.reserved_ram,
+            cpu_overcommit: rec.cpu_overcommit,
+            ram_overcommit: rec.ram_overcommit,
+            labels,
         }))
     }
 
+    /// Returns the labels of all the agents keyed by the agent guid
+    pub async fn get_all_labels<'c, E>(executor: E) -> Result<HashMap<String, Labels>>
+    where
+        E: Executor<'c, Database = Sqlite>,
+    {
+        let rec = sqlx::query!(
+            r#"
+            SELECT agent_guid, key, value
+            FROM agent_labels
+            "#
+        )
+        .fetch_all(executor)
+        .await?;
+
+        let mut labels: HashMap<String, Labels> = HashMap::new();
+        for label in rec {
+            labels
+                .entry(label.agent_guid)
+                .or_default()
+                .insert(label.key, label.value);
+        }
+        Ok(labels)
+    }
+
+    async fn replace_labels(
+        guid: &str,
+        labels: &Labels,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM agent_labels
+            WHERE agent_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for (key, value) in labels {
+            sqlx::query!(
+                r#"
+                INSERT INTO agent_labels (agent_guid, key, value)
+                VALUES($1, $2, $3)
+                "#,
+                guid,
+                key,
+                value
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces all the labels of the agent
+    pub async fn set_labels(guid: &str, labels: &Labels, pool: &SqlitePool) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        let rec = sqlx::query!(
+            r#"
+            SELECT guid
+            FROM agents
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        if rec.is_none() {
+            return Ok(false);
+        }
+
+        Self::replace_labels(guid, labels, &mut tx).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Picks an agent to run a task on behalf of the job. Agents with a live
+    /// container of the job are preferred, then the ones which ran the job
+    /// and then any agent of the job type
+    pub async fn pick_for_job(job_guid: &str, pool: &SqlitePool) -> Result<Option<String>> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT agents.guid
+            FROM jobs
+            INNER JOIN agents ON agents.guid = jobs.agent_guid
+            WHERE jobs.collection_guid = $1 AND agents.status = 'up'
+            ORDER BY jobs.status = 'alive' DESC
+            LIMIT 1
+            "#,
+            job_guid
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        if let Some(rec) = rec {
+            return Ok(Some(rec.guid));
+        }
+
+        let rec = sqlx::query!(
+            r#"
+            SELECT agents.guid
+            FROM agents
+            INNER JOIN job_collection ON job_collection.agent_type = agents.agent_type
+            WHERE job_collection.guid = $1 AND agents.status = 'up' AND agents.state != 'maintenance'
+            ORDER BY agents.free_cpus DESC
+            LIMIT 1
+            "#,
+            job_guid
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(rec.map(|rec| rec.guid))
+    }
+
+    /// Checks any agent of the type is known, regardless of its status
+    pub async fn has_agent_type(agent_type: &str, pool: &SqlitePool) -> Result<bool> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM agents
+            WHERE agent_type = $1
+            "#,
+            agent_type
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(rec.count > 0)
+    }
+
     pub async fn create(agent: Agent, pool: &SqlitePool) -> Result<Agent> {
         let mut tx = pool.begin().await?;
 
         sqlx::query!(
             r#"
-            INSERT INTO agents (guid, description, agent_type, endpoint, status, cpus, ram)
-            VALUES($1, $2, $3, $4, $5, NULL, NULL)
+            INSERT INTO agents (guid, description, agent_type, endpoint, status, cpus, ram, created_by)
+            VALUES($1, $2, $3, $4, $5, NULL, NULL, $6)
             "#,
             agent.guid,
             agent.description,
             agent.agent_type,
             agent.endpoint,
-            agent.status
+            agent.status,
+            agent.created_by
         )
         .execute(&mut tx)
         .await?;
 
+        Self::replace_labels(&agent.guid, &agent.labels, &mut tx).await?;
+
         tx.commit().await.unwrap();
 
         Ok(agent)
     }
 
+    /// Checks the registering agent owns the guid. Returns None for an
+    /// unknown agent, the agents created from the UI have no secret
+    pub async fn verify_secret(
+        guid: &str,
+        secret: &str,
+        pool: &SqlitePool,
+    ) -> Result<Option<bool>> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT secret_hash
+            FROM agents
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(rec.map(|rec| rec.secret_hash == Some(hash_token(secret))))
+    }
+
+    pub fn new_secret() -> String {
+        new_token()
+    }
+
+    /// Creates the agent on its first registration, the later ones update its
+    /// endpoint, version, labels and resources. The reported cpus and ram
+    /// already take the reservation and the overcommit into account. The
+    /// resources taken by the running jobs stay reserved. The secret is only
+    /// stored for a new agent
+    pub async fn register(
+        guid: &str,
+        secret: Option<&str>,
+        req: &AgentRegisterRequest,
+        pool: &SqlitePool,
+    ) -> Result<()> {
+        let cpus = i64::try_from(req.cpus)?;
+        let ram = i64::try_from(req.ram)?;
+        let reserved_cpus = i64::try_from(req.reserved_cpus)?;
+        let reserved_ram = i64::try_from(req.reserved_ram)?;
+        let cpu_overcommit = overcommit(req.cpu_overcommit);
+        let ram_overcommit = overcommit(req.ram_overcommit);
+        let now = chrono::offset::Utc::now().to_string();
+        let secret_hash = secret.map(hash_token);
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO agents (guid, description, agent_type, endpoint, status, free_cpus, free_ram, cpus, ram, last_seen, created_by, version, secret_hash, reserved_cpus, reserved_ram, cpu_overcommit, ram_overcommit)
+            VALUES($1, $2, $3, $4, 'down', $5, $6, $5, $6, $7, 'registration', $8, $9, $10, $11, $12, $13)
+            ON CONFLICT(guid) DO UPDATE SET
+                description = $2,
+                endpoint = $4,
+                free_cpus = COALESCE(free_cpus, 0) + $5 - COALESCE(cpus, 0),
+                free_ram = COALESCE(free_ram, 0) + $6 - COALESCE(ram, 0),
+                cpus = $5,
+                ram = $6,
+                last_seen = $7,
+                version = $8,
+                reserved_cpus = $10,
+                reserved_ram = $11,
+                cpu_overcommit = $12,
+                ram_overcommit = $13
+            "#,
+            guid,
+            req.description,
+            req.agent_type,
+            req.endpoint,
+            cpus,
+            ram,
+            now,
+            req.version,
+            secret_hash,
+            reserved_cpus,
+            reserved_ram,
+            cpu_overcommit,
+            ram_overcommit
+        )
+        .execute(&mut tx)
+        .await?;
+
+        Self::replace_labels(guid, &req.labels, &mut tx).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
     pub async fn delete(guid: String, pool: &SqlitePool) -> Result<String> {
         let mut tx = pool.begin().await?;
 
@@ -135,6 +523,16 @@ impl Agent {
         .execute(&mut tx)
         .await?;
 
+        sqlx::query!(
+            r#"
+            DELETE FROM agent_labels
+            WHERE agent_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
         tx.commit().await.unwrap();
         Ok(guid)
     }
@@ -146,17 +544,288 @@ impl Agent {
     ) -> Result<bool> {
         let cpus = i64::try_from(sys_info.cpus).unwrap_or(0);
         let ram = i64::try_from(sys_info.ram).unwrap_or(0);
+        let reserved_cpus = i64::try_from(sys_info.reserved_cpus).unwrap_or(0);
+        let reserved_ram = i64::try_from(sys_info.reserved_ram).unwrap_or(0);
+        let cpu_overcommit = overcommit(sys_info.cpu_overcommit);
+        let ram_overcommit = overcommit(sys_info.ram_overcommit);
         let rows_affected = sqlx::query!(
             r#"
             UPDATE agents
-            SET free_cpus = $2, free_ram = $3, cpus = $4, ram = $5
+            SET free_cpus = $2, free_ram = $3, cpus = $4, ram = $5, reserved_cpus = $6,
+                reserved_ram = $7, cpu_overcommit = $8, ram_overcommit = $9
             WHERE guid = $1
             "#,
             guid,
             cpus,
             ram,
             cpus,
-            ram
+            ram,
+            reserved_cpus,
+            reserved_ram,
+            cpu_overcommit,
+            ram_overcommit
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Brings the resources of the agent in line with the host. The free
+    /// resources are recomputed from the totals reported by the agent minus
+    /// the ones held by its sub-jobs, the containers running on the agent
+    /// without a reservation count as well. Returns the mismatches found on
+    /// the way, they are kept in drift until the next reconciliation
+    pub async fn reconcile(
+        guid: &str,
+        sys_info: &SysInfo,
+        running: &[JobCreateRequest],
+        pool: &SqlitePool,
+    ) -> Result<Option<String>> {
+        let cpus = i64::try_from(sys_info.cpus)?;
+        let ram = i64::try_from(sys_info.ram)?;
+        let available_ram = i64::try_from(sys_info.available_ram)?;
+        let disk_total = i64::try_from(sys_info.disk_total)?;
+        let disk_free = i64::try_from(sys_info.disk_free)?;
+        let reserved_cpus = i64::try_from(sys_info.reserved_cpus)?;
+        let reserved_ram = i64::try_from(sys_info.reserved_ram)?;
+        let cpu_overcommit = overcommit(sys_info.cpu_overcommit);
+        let ram_overcommit = overcommit(sys_info.ram_overcommit);
+        let now = chrono::offset::Utc::now().to_string();
+
+        let mut tx = pool.begin().await?;
+
+        let agent = sqlx::query!(
+            r#"
+            SELECT free_cpus, free_ram, cpus, ram
+            FROM agents
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Agent {} not found", guid))?;
+
+        // Paused sub-jobs have given their cpus back but hold their ram
+        let reserved = sqlx::query!(
+            r#"
+            SELECT collection_guid, cpus, ram, freed
+            FROM jobs
+            WHERE agent_guid = $1 AND (freed = 0 OR status = 'paused')
+            "#,
+            guid
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut drift = Vec::new();
+        let mut used_cpus: i64 = reserved
+            .iter()
+            .filter(|job| !job.freed)
+            .map(|job| job.cpus)
+            .sum();
+        let mut used_ram: i64 = reserved.iter().map(|job| job.ram).sum();
+        for job in running {
+            if reserved
+                .iter()
+                .all(|reserved| reserved.collection_guid != job.job_guid)
+            {
+                drift.push(format!(
+                    "job {} runs without reserved resources",
+                    job.job_guid
+                ));
+                if job.status != "paused" {
+                    used_cpus += i64::try_from(job.cpus)?;
+                }
+                used_ram += i64::try_from(job.ram)?;
+            }
+        }
+
+        let free_cpus = std::cmp::max(cpus - used_cpus, 0);
+        let free_ram = std::cmp::max(ram - used_ram, 0);
+
+        if agent.cpus != Some(cpus) {
+            drift.push(format!(
+                "cpus changed from {} to {}",
+                agent.cpus.unwrap_or(0),
+                cpus
+            ));
+        }
+        if agent.ram != Some(ram) {
+            drift.push(format!(
+                "ram changed from {} to {}",
+                agent.ram.unwrap_or(0),
+                ram
+            ));
+        }
+        if agent.free_cpus != Some(free_cpus) {
+            drift.push(format!(
+                "free cpus were {} instead of {}",
+                agent.free_cpus.unwrap_or(0),
+                free_cpus
+            ));
+        }
+        if agent.free_ram != Some(free_ram) {
+            drift.push(format!(
+                "free ram was {} instead of {}",
+                agent.free_ram.unwrap_or(0),
+                free_ram
+            ));
+        }
+        if used_cpus > cpus || used_ram > ram {
+            drift.push(format!(
+                "sub-jobs take {} cpus and {} ram, more than the host has",
+                used_cpus, used_ram
+            ));
+        }
+        // The reserved RAM isn't meant for the jobs even when it is available
+        let usable_ram =
+            (std::cmp::max(available_ram - reserved_ram, 0) as f64 * ram_overcommit) as i64;
+        if usable_ram < free_ram {
+            drift.push(format!(
+                "only {} of the {} free ram is available on the host",
+                usable_ram, free_ram
+            ));
+        }
+        let drift = if drift.is_empty() {
+            None
+        } else {
+            Some(drift.join(", "))
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE agents
+            SET cpus = $2, ram = $3, free_cpus = $4, free_ram = $5, load_avg = $6,
+                available_ram = $7, disk_total = $8, disk_free = $9, drift = $10, reconciled_at = $11,
+                reserved_cpus = $12, reserved_ram = $13, cpu_overcommit = $14, ram_overcommit = $15
+            WHERE guid = $1
+            "#,
+            guid,
+            cpus,
+            ram,
+            free_cpus,
+            free_ram,
+            sys_info.load_avg,
+            available_ram,
+            disk_total,
+            disk_free,
+            drift,
+            now,
+            reserved_cpus,
+            reserved_ram,
+            cpu_overcommit,
+            ram_overcommit
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(drift)
+    }
+
+    /// Amount of the sub-jobs running on the agent
+    pub async fn count_running_jobs<'c, E>(guid: &str, executor: E) -> Result<i64>
+    where
+        E: Executor<'c, Database = Sqlite>,
+    {
+        let rec = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM jobs
+            WHERE agent_guid = $1 AND status IN ('init', 'alive', 'paused')
+            "#,
+            guid
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(rec.count)
+    }
+
+    /// Changes the state of the agent. A draining agent without running
+    /// sub-jobs goes straight to maintenance. Returns the new state, None if
+    /// the agent doesn't exist
+    pub async fn set_state(guid: &str, state: &str, pool: &SqlitePool) -> Result<Option<String>> {
+        let mut tx = pool.begin().await?;
+
+        let state = match state {
+            "draining" if Self::count_running_jobs(guid, &mut tx).await? == 0 => "maintenance",
+            state => state,
+        };
+
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE agents
+            SET state = $2
+            WHERE guid = $1
+            "#,
+            guid,
+            state
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        if rows_affected == 0 {
+            return Ok(None);
+        }
+        Ok(Some(state.to_string()))
+    }
+
+    /// Puts the draining agent into maintenance once its last sub-job is over
+    pub async fn finish_draining(guid: &str, pool: &SqlitePool) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        if Self::count_running_jobs(guid, &mut tx).await? != 0 {
+            return Ok(false);
+        }
+
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE agents
+            SET state = 'maintenance'
+            WHERE guid = $1 AND state = 'draining'
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn get_last_seq(guid: &str, pool: &SqlitePool) -> Result<u64> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT last_seq
+            FROM agents
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(rec.last_seq as u64)
+    }
+
+    pub async fn set_last_seq(guid: &str, seq: u64, pool: &SqlitePool) -> Result<bool> {
+        let seq = i64::try_from(seq)?;
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE agents
+            SET last_seq = $2
+            WHERE guid = $1
+            "#,
+            guid,
+            seq
         )
         .execute(pool)
         .await?
@@ -166,14 +835,36 @@ impl Agent {
     }
 
     pub async fn update_status(guid: &str, status: &str, pool: &SqlitePool) -> Result<bool> {
+        let now = chrono::offset::Utc::now().to_string();
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE agents
+            SET status = $2,
+                status_changed = CASE WHEN status != $2 THEN $3 ELSE status_changed END,
+                last_seen = CASE WHEN $2 == 'up' THEN $3 ELSE last_seen END
+            WHERE guid = $1
+            "#,
+            guid,
+            status,
+            now
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn touch(guid: &str, pool: &SqlitePool) -> Result<bool> {
+        let now = chrono::offset::Utc::now().to_string();
         let rows_affected = sqlx::query!(
             r#"
             UPDATE agents
-            SET status = $2
+            SET last_seen = $2
             WHERE guid = $1
             "#,
             guid,
-            status
+            now
         )
         .execute(pool)
         .await?
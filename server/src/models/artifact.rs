@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+
+/// A target or a corpus stored once in NFS_DIR/artifacts/<hash>, the hash is
+/// the SHA3-256 of the content
+#[derive(Serialize, Deserialize, Default)]
+pub struct Artifact {
+    pub hash: String,
+    pub size: i64,
+    pub creation_date: String,
+    pub created_by: String,
+    pub last_used: String,
+    /// Amount of the jobs referring to the artifact
+    pub refs: i64,
+}
+
+/// Checks the reference is a SHA3-256 digest, so it can't escape the store
+pub fn valid_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+impl Artifact {
+    pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Artifact>> {
+        Ok(sqlx::query_as!(
+            Artifact,
+            r#"
+            SELECT hash, size, creation_date, created_by, last_used,
+                (SELECT COUNT(*) FROM job_artifacts WHERE job_artifacts.hash = artifacts.hash) as "refs!: i64"
+            FROM artifacts
+            ORDER BY creation_date DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn find(hash: &str, pool: &SqlitePool) -> Result<Option<Artifact>> {
+        Ok(sqlx::query_as!(
+            Artifact,
+            r#"
+            SELECT hash, size, creation_date, created_by, last_used,
+                (SELECT COUNT(*) FROM job_artifacts WHERE job_artifacts.hash = artifacts.hash) as "refs!: i64"
+            FROM artifacts
+            WHERE hash = $1
+            "#,
+            hash
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Records the stored artifact, an already known one is only touched
+    pub async fn store(hash: &str, size: i64, created_by: &str, pool: &SqlitePool) -> Result<()> {
+        let now = chrono::offset::Utc::now().to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO artifacts (hash, size, creation_date, created_by, last_used)
+            VALUES($1, $2, $3, $4, $3)
+            ON CONFLICT(hash) DO UPDATE SET last_used = $3
+            "#,
+            hash,
+            size,
+            now,
+            created_by
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the artifact as used, so it isn't collected before the job
+    /// referring to it is created. Returns false for an unknown artifact
+    pub async fn touch(hash: &str, pool: &SqlitePool) -> Result<bool> {
+        let now = chrono::offset::Utc::now().to_string();
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE artifacts
+            SET last_used = $2
+            WHERE hash = $1
+            "#,
+            hash,
+            now
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn add_refs(
+        collection_guid: &str,
+        artifacts: &BTreeMap<String, String>,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<()> {
+        for (file, hash) in artifacts {
+            sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO job_artifacts (collection_guid, file, hash)
+                VALUES($1, $2, $3)
+                "#,
+                collection_guid,
+                file,
+                hash
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_refs(
+        collection_guid: &str,
+        pool: &SqlitePool,
+    ) -> Result<BTreeMap<String, String>> {
+        Ok(sqlx::query!(
+            r#"
+            SELECT file, hash
+            FROM job_artifacts
+            WHERE collection_guid = $1
+            "#,
+            collection_guid
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|rec| (rec.file, rec.hash))
+        .collect())
+    }
+
+    pub async fn remove_refs(
+        collection_guid: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM job_artifacts
+            WHERE collection_guid = $1
+            "#,
+            collection_guid
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the artifacts no job refers to which haven't been used since
+    /// the cutoff. Returns their hashes, so the files can be removed
+    pub async fn collect(cutoff: &str, pool: &SqlitePool) -> Result<Vec<String>> {
+        let mut tx = pool.begin().await?;
+
+        let hashes = sqlx::query!(
+            r#"
+            SELECT hash
+            FROM artifacts
+            WHERE last_used < $1 AND hash NOT IN (SELECT hash FROM job_artifacts)
+            "#,
+            cutoff
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|rec| rec.hash)
+        .collect::<Vec<String>>();
+
+        for hash in hashes.iter() {
+            sqlx::query!(
+                r#"
+                DELETE FROM artifacts
+                WHERE hash = $1
+                "#,
+                hash
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(hashes)
+    }
+}
@@ -0,0 +1,261 @@
+use actix_http::body::BoxBody;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, Sqlite, SqlitePool, Transaction};
+
+use crate::models::Crash;
+
+/// Amount of the top-most frames forming a crash signature
+const SIGNATURE_FRAMES: usize = 3;
+
+/// Frames of the sanitizers, libc and the crash handling machinery which
+/// don't tell anything about the bug itself
+const IGNORED_FRAMES: &[&str] = &[
+    "__asan",
+    "__msan",
+    "__ubsan",
+    "__sanitizer",
+    "__interceptor",
+    "__GI_",
+    "__libc",
+    "__assert",
+    "__pthread_kill",
+    "abort",
+    "raise",
+    "pthread_kill",
+];
+
+#[derive(Serialize, Deserialize, FromRow, Default)]
+pub struct Bucket {
+    pub id: i64,
+    pub signature: String,
+    pub crash_type: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub hits: i64,
+}
+
+impl Responder for Bucket {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::Ok().json(&self)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct BucketInfoResponse {
+    pub bucket: Bucket,
+    pub crashes: Vec<Crash>,
+}
+
+fn parse_frame(line: &str) -> Option<String> {
+    let line = line.trim();
+    if !line.starts_with('#') {
+        return None;
+    }
+
+    // Skip the frame number: "#0 0x4005d4 in main /src/a.c:10:5" or "#0  main () at a.c:3"
+    let rest = line.split_once(char::is_whitespace)?.1.trim_start();
+    let rest = match rest.find(" in ") {
+        Some(idx) => &rest[idx + 4..],
+        None => rest,
+    };
+
+    let func = rest.split_whitespace().next()?;
+    // Strip the arguments and the "+0x1f" offsets
+    let func = func.split(['(', '+']).next().unwrap_or(func);
+    if func.is_empty() || func == "??" || func.starts_with("0x") {
+        return None;
+    }
+
+    if IGNORED_FRAMES.iter().any(|prefix| func.starts_with(prefix)) {
+        return None;
+    }
+
+    Some(func.to_string())
+}
+
+fn top_frames(trace: &str) -> Vec<String> {
+    let mut frames: Vec<String> = Vec::new();
+    for frame in trace.lines().filter_map(parse_frame) {
+        // Recursion shouldn't change the signature
+        if frames.last() == Some(&frame) {
+            continue;
+        }
+        frames.push(frame);
+        if frames.len() == SIGNATURE_FRAMES {
+            break;
+        }
+    }
+    frames
+}
+
+/// Computes a normalized signature of the crash from the result of the
+/// analysis made by grpc_handler.py. Returns the signature and the crash type
+pub fn crash_signature(analyzed: &str) -> Option<(String, String)> {
+    let analyzed: Value = serde_json::from_str(analyzed).ok()?;
+
+    let crash_type = analyzed["clusterfuzz"]["type"]
+        .as_str()
+        .filter(|crash_type| !crash_type.is_empty())
+        .or_else(|| analyzed["gdb"]["exploitable"]["Short description"].as_str())
+        .unwrap_or("Unknown")
+        .to_string();
+
+    let mut frames = analyzed["clusterfuzz"]["stacktrace"]
+        .as_str()
+        .map(top_frames)
+        .unwrap_or_default();
+    if frames.is_empty() {
+        frames = analyzed["gdb"]["backtrace"]
+            .as_str()
+            .map(top_frames)
+            .unwrap_or_default();
+    }
+
+    if frames.is_empty() {
+        return None;
+    }
+
+    Some((
+        format!("{} | {}", crash_type, frames.join(" | ")),
+        crash_type,
+    ))
+}
+
+impl Bucket {
+    /// Puts the crash into the bucket of its signature, creating the bucket on the first hit
+    pub async fn assign(
+        crash_guid: &str,
+        analyzed: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<Option<i64>> {
+        let (signature, crash_type) = match crash_signature(analyzed) {
+            Some(signature) => signature,
+            None => return Ok(None),
+        };
+
+        let now = chrono::offset::Utc::now().to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO buckets (signature, crash_type, first_seen, last_seen, hits)
+            VALUES($1, $2, $3, $3, 1)
+            ON CONFLICT(signature) DO UPDATE SET last_seen = $3, hits = hits + 1
+            "#,
+            signature,
+            crash_type,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let rec = sqlx::query!(
+            r#"
+            SELECT id as "id!"
+            FROM buckets
+            WHERE signature = $1
+            "#,
+            signature
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE crashes
+            SET bucket_id = $2, signature = $3
+            WHERE guid = $1
+            "#,
+            crash_guid,
+            rec.id,
+            signature
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(Some(rec.id))
+    }
+
+    pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Bucket>> {
+        Ok(sqlx::query_as!(
+            Bucket,
+            r#"
+            SELECT id, signature, crash_type, first_seen, last_seen, hits
+            FROM buckets
+            ORDER BY last_seen DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn get_bucket(id: i64, pool: &SqlitePool) -> Result<BucketInfoResponse> {
+        let bucket = sqlx::query_as!(
+            Bucket,
+            r#"
+            SELECT id, signature, crash_type, first_seen, last_seen, hits
+            FROM buckets
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let crashes = Crash::get_all_crashes_by_bucket(id, pool).await?;
+
+        Ok(BucketInfoResponse { bucket, crashes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_a_signature_from_a_sanitizer_stacktrace() {
+        let analyzed = serde_json::json!({
+            "clusterfuzz": {
+                "type": "Heap-buffer-overflow\nREAD 4",
+                "stacktrace": "==1==ERROR: AddressSanitizer: heap-buffer-overflow\n\
+                    #0 0x4c2c3f in parse_header /src/parser.c:42:13\n\
+                    #1 0x4c2f00 in parse /src/parser.c:80:5\n\
+                    #2 0x4c3000 in main /src/main.c:10:3\n\
+                    #3 0x7f0000 in __libc_start_main\n"
+            }
+        });
+
+        let (signature, _) = crash_signature(&analyzed.to_string()).unwrap();
+        assert_eq!(
+            signature,
+            "Heap-buffer-overflow\nREAD 4 | parse_header | parse | main"
+        );
+    }
+
+    #[test]
+    fn it_falls_back_to_the_gdb_backtrace() {
+        let analyzed = serde_json::json!({
+            "clusterfuzz": { "type": "", "stacktrace": "" },
+            "gdb": {
+                "backtrace": "#0  __GI_raise (sig=sig@entry=6) at raise.c:50\n\
+                    #1  0x00007ffff7df2859 in __GI_abort () at abort.c:79\n\
+                    #2  0x0000555555555189 in vuln (buf=0x0) at target.c:7\n\
+                    #3  0x00005555555551c2 in main () at target.c:15",
+                "exploitable": { "Short description": "AbortSignal" }
+            }
+        });
+
+        let (signature, crash_type) = crash_signature(&analyzed.to_string()).unwrap();
+        assert_eq!(crash_type, "AbortSignal");
+        assert_eq!(signature, "AbortSignal | vuln | main");
+    }
+
+    #[test]
+    fn it_skips_crashes_without_frames() {
+        assert!(crash_signature("{}").is_none());
+        assert!(crash_signature("not a json").is_none());
+    }
+}
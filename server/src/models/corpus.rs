@@ -0,0 +1,122 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+
+/// Settings of a named corpus saved from the findings of a job
+#[derive(Serialize, Deserialize)]
+pub struct CorpusSaveRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Runs afl-cmin over the corpus and the findings on an agent first
+    #[serde(default)]
+    pub minimize: bool,
+}
+
+/// A reusable corpus stored once in NFS_DIR/corpora/<guid>.zip
+#[derive(Serialize, Deserialize, FromRow, Default)]
+pub struct Corpus {
+    pub guid: String,
+    pub name: String,
+    pub description: String,
+    pub creation_date: String,
+    pub created_by: String,
+    /// Job the corpus has been taken from, None for an uploaded one
+    pub source_job: Option<String>,
+    pub size: i64,
+    /// minimizing, ready or error, only the ready corpora can be used
+    pub status: String,
+    pub last_msg: String,
+}
+
+impl Corpus {
+    pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Corpus>> {
+        Ok(sqlx::query_as!(
+            Corpus,
+            r#"
+            SELECT guid, name, description, creation_date, created_by, source_job, size, status, last_msg
+            FROM corpora
+            ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Looks the corpus up by its guid or its name
+    pub async fn find(reference: &str, pool: &SqlitePool) -> Result<Option<Corpus>> {
+        Ok(sqlx::query_as!(
+            Corpus,
+            r#"
+            SELECT guid, name, description, creation_date, created_by, source_job, size, status, last_msg
+            FROM corpora
+            WHERE guid = $1 OR name = $1
+            "#,
+            reference
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    pub async fn create(corpus: &Corpus, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO corpora (guid, name, description, creation_date, created_by, source_job, size, status, last_msg)
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            corpus.guid,
+            corpus.name,
+            corpus.description,
+            corpus.creation_date,
+            corpus.created_by,
+            corpus.source_job,
+            corpus.size,
+            corpus.status,
+            corpus.last_msg
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_status(
+        guid: &str,
+        status: &str,
+        last_msg: &str,
+        size: Option<i64>,
+        pool: &SqlitePool,
+    ) -> Result<bool> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE corpora
+            SET status = $2, last_msg = $3, size = COALESCE($4, size)
+            WHERE guid = $1
+            "#,
+            guid,
+            status,
+            last_msg,
+            size
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn delete(guid: &str, pool: &SqlitePool) -> Result<bool> {
+        let rows_affected = sqlx::query!(
+            r#"
+            DELETE FROM corpora
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+}
@@ -6,12 +6,14 @@ use sha3::{Digest, Sha3_256};
 use sqlx::{FromRow, SqlitePool};
 use uuid::Uuid;
 
+use crate::models::Bucket;
 use crate::protos::agent::CrashMsg;
 use crate::utils::get_job_dir;
 
 #[derive(Serialize, Deserialize, FromRow, Default)]
 pub struct CrashStats {
     pub total: u64,
+    pub unique: u64,
 }
 
 #[derive(Serialize, Deserialize, FromRow, Default)]
@@ -23,6 +25,11 @@ pub struct Crash {
     pub hash: String,
     pub creation_date: String,
     pub size: i64,
+    pub signature: Option<String>,
+    pub bucket_id: Option<i64>,
+    pub minimized: Option<String>,
+    pub minimize_status: Option<String>,
+    pub minimize_msg: Option<String>,
 }
 
 impl Crash {
@@ -42,6 +49,8 @@ impl Crash {
         let metadata = fs::metadata(&crash_path)?;
         let size = i64::try_from(metadata.len())?;
 
+        let mut tx = pool.begin().await?;
+
         sqlx::query!(
             r#"
             INSERT INTO crashes (guid, name, collection_guid, analyzed, hash, creation_date, size)
@@ -55,6 +64,69 @@ impl Crash {
             now,
             size
         )
+        .execute(&mut tx)
+        .await?;
+
+        if let Some(analyzed) = &crash.analyzed {
+            Bucket::assign(&guid, analyzed, &mut tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Stores the result of an on-demand analysis and moves the crash into
+    /// the bucket of its new signature
+    pub async fn set_analyzed(guid: &str, analyzed: &str, pool: &SqlitePool) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE buckets
+            SET hits = hits - 1
+            WHERE id = (SELECT bucket_id FROM crashes WHERE guid = $1)
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE crashes
+            SET analyzed = $2, signature = NULL, bucket_id = NULL
+            WHERE guid = $1
+            "#,
+            guid,
+            analyzed
+        )
+        .execute(&mut tx)
+        .await?;
+
+        Bucket::assign(guid, analyzed, &mut tx).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn set_minimize_status(
+        guid: &str,
+        status: &str,
+        last_msg: &str,
+        minimized: Option<&str>,
+        pool: &SqlitePool,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE crashes
+            SET minimize_status = $2, minimize_msg = $3, minimized = COALESCE($4, minimized)
+            WHERE guid = $1
+            "#,
+            guid,
+            status,
+            last_msg,
+            minimized
+        )
         .execute(pool)
         .await?;
 
@@ -65,7 +137,7 @@ impl Crash {
         Ok(sqlx::query_as!(
             Crash,
             r#"
-            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size
+            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size, signature, bucket_id, minimized, minimize_status, minimize_msg
             FROM crashes
             "#
         )
@@ -77,7 +149,7 @@ impl Crash {
         Ok(sqlx::query_as!(
             Crash,
             r#"
-            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size
+            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size, signature, bucket_id, minimized, minimize_status, minimize_msg
             FROM crashes
             WHERE collection_guid = $1
             "#,
@@ -87,10 +159,28 @@ impl Crash {
         .await?)
     }
 
+    pub async fn get_all_crashes_by_bucket(
+        bucket_id: i64,
+        pool: &SqlitePool,
+    ) -> Result<Vec<Crash>> {
+        Ok(sqlx::query_as!(
+            Crash,
+            r#"
+            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size, signature, bucket_id, minimized, minimize_status, minimize_msg
+            FROM crashes
+            WHERE bucket_id = $1
+            ORDER BY creation_date DESC
+            "#,
+            bucket_id
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
     pub async fn get_crash_stats(pool: &SqlitePool) -> Result<CrashStats> {
         let rec = sqlx::query!(
             r#"
-            SELECT COUNT(*) as total
+            SELECT COUNT(*) as total, COUNT(DISTINCT bucket_id) as "unique!: i64"
             FROM crashes
             "#
         )
@@ -99,6 +189,7 @@ impl Crash {
 
         Ok(CrashStats {
             total: rec.total as u64,
+            unique: rec.unique as u64,
         })
     }
 
@@ -106,7 +197,7 @@ impl Crash {
         Ok(sqlx::query_as!(
             Crash,
             r#"
-            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size
+            SELECT guid, name, collection_guid, analyzed, hash, creation_date, size, signature, bucket_id, minimized, minimize_status, minimize_msg
             FROM crashes
             WHERE guid = $1
             "#,
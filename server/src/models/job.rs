@@ -1,12 +1,63 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
 use crate::handlers::agent::JobInfo;
-use crate::protos::agent::{JobCreateRequest, JobInfoContainerList};
+use crate::models::{format_labels, parse_labels, Agent, Artifact, Labels};
+use crate::protos::agent::{self, JobCreateRequest, JobInfoContainerList};
+use crate::scheduler::{get_scheduler, satisfies, AgentResources};
 
 use actix_http::body::BoxBody;
 use actix_web::{HttpRequest, HttpResponse, Responder};
 use anyhow::Result;
 use log::info;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Row, SqlitePool};
+use sqlx::{FromRow, Row, Sqlite, SqlitePool, Transaction};
+
+/// Modes of afl-fuzz the target can be run in
+pub const FUZZER_MODES: &[&str] = &["qemu", "frida", "unicorn", "instrumented"];
+
+/// Power schedules of afl-fuzz
+pub const POWER_SCHEDULES: &[&str] = &[
+    "explore", "mmopt", "exploit", "fast", "coe", "lin", "quad", "rare", "seek",
+];
+
+/// Options of the afl-fuzz instances of the job, stored as JSON in
+/// job_collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct FuzzerOptions {
+    pub mode: String,
+    /// Dictionary in target.zip passed with -x
+    pub dict: String,
+    /// Passes the input as a file with @@ instead of stdin
+    pub input_file: bool,
+    pub extra_args: Vec<String>,
+    /// Power schedules the secondary instances take in turn
+    pub schedules: Vec<String>,
+}
+
+impl Default for FuzzerOptions {
+    fn default() -> Self {
+        FuzzerOptions {
+            mode: "qemu".to_string(),
+            dict: String::new(),
+            input_file: false,
+            extra_args: Vec::new(),
+            schedules: Vec::new(),
+        }
+    }
+}
+
+impl From<&FuzzerOptions> for agent::FuzzerOptions {
+    fn from(options: &FuzzerOptions) -> Self {
+        agent::FuzzerOptions {
+            mode: options.mode.clone(),
+            dict: options.dict.clone(),
+            input_file: options.input_file,
+            extra_args: options.extra_args.clone(),
+            schedules: options.schedules.clone(),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, FromRow, Default)]
 pub struct JobCollection {
@@ -22,6 +73,11 @@ pub struct JobCollection {
     pub target: String,
     pub corpus: String,
     pub status: String,
+    pub created_by: String,
+    pub priority: i64,
+    pub queue_position: Option<u64>,
+    pub env: BTreeMap<String, String>,
+    pub fuzzer: FuzzerOptions,
 }
 
 impl Responder for JobCollection {
@@ -56,6 +112,17 @@ pub struct JobInfoResponse {
     pub jobs: Vec<Job>,
 }
 
+impl JobInfoResponse {
+    /// Limits of the task containers started for the job, the ones of its
+    /// first sub-job or of the whole job while it's queued
+    pub fn task_limits(&self) -> (u64, u64) {
+        match self.jobs.first() {
+            Some(job) => (job.cpus, job.ram),
+            None => (self.job_collection.cpus, self.job_collection.ram),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, FromRow, Default)]
 pub struct JobStats {
     pub alive: u64,
@@ -71,11 +138,31 @@ impl Responder for JobStats {
     }
 }
 
+/// Numbers the queued jobs of each agent type in the order they get scheduled
+fn set_queue_positions(collections: &mut [JobCollection]) {
+    let mut queued: Vec<&mut JobCollection> = collections
+        .iter_mut()
+        .filter(|job| job.status == "queued")
+        .collect();
+    queued.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then_with(|| a.creation_date.cmp(&b.creation_date))
+    });
+
+    let mut positions: HashMap<String, u64> = HashMap::new();
+    for job in queued {
+        let position = positions.entry(job.agent_type.clone()).or_insert(0);
+        *position += 1;
+        job.queue_position = Some(*position);
+    }
+}
+
 impl Job {
     pub async fn get_all_collections(pool: &SqlitePool) -> Result<Vec<JobCollection>> {
-        let job_collection = sqlx::query!(
+        let mut job_collection = sqlx::query!(
             r#"
-              SELECT guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status
+              SELECT guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status, created_by, priority, env, fuzzer
               FROM job_collection
             "#
         )
@@ -94,59 +181,77 @@ impl Job {
             timeout: rec.timeout,
             target: rec.target,
             corpus: rec.corpus,
-            status: rec.status
+            status: rec.status,
+            created_by: rec.created_by,
+            priority: rec.priority,
+            queue_position: None,
+            env: serde_json::from_str(&rec.env).unwrap_or_default(),
+            fuzzer: serde_json::from_str(&rec.fuzzer).unwrap_or_default(),
         })
-        .collect();
+        .collect::<Vec<JobCollection>>();
+
+        set_queue_positions(&mut job_collection);
 
         Ok(job_collection)
     }
 
-    pub async fn schedule_job(job_info: &JobInfo, pool: &SqlitePool) -> Result<Vec<JobRequest>> {
-        let mut tx = pool.begin().await?;
+    /// Splits the job between the agents with the strategy of the job and
+    /// reserves their resources. Returns None when the agents don't have
+    /// enough free resources right now
+    async fn schedule_sub_jobs(
+        job_info: &JobInfo,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<Option<Vec<JobRequest>>> {
+        let scheduler = get_scheduler(&job_info.strategy)
+            .ok_or_else(|| anyhow::anyhow!("Unknown scheduling strategy {}", job_info.strategy))?;
 
-        let rec = sqlx::query!(
+        let mut labels = Agent::get_all_labels(&mut *tx).await?;
+        let agents: Vec<AgentResources> = sqlx::query!(
             r#"
             SELECT guid, free_cpus, free_ram
             FROM agents
-            WHERE status == 'up' AND agent_type = $1 AND free_cpus > 0 AND free_ram > 0
-            ORDER BY free_cpus DESC
+            WHERE status == 'up' AND state = 'active' AND agent_type = $1 AND free_cpus > 0
             "#,
             job_info.agent_type,
         )
-        .fetch_all(&mut tx)
-        .await?;
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|rec| AgentResources {
+            labels: labels.remove(&rec.guid).unwrap_or_default(),
+            guid: rec.guid,
+            free_cpus: rec.free_cpus.unwrap_or(0) as u64,
+            free_ram: rec.free_ram.unwrap_or(0) as u64,
+        })
+        .collect();
 
-        let total_free_cpus: u64 = rec
-            .iter()
-            .map(|rec| rec.free_cpus.unwrap_or(0) as u64)
-            .sum();
-        if total_free_cpus < job_info.cpus {
-            return Err(anyhow::anyhow!("Insuficient amount of free cpus"));
-        }
+        let placements = match scheduler.place(job_info, &agents) {
+            Some(placements) => placements,
+            None => return Ok(None),
+        };
 
-        let mut rest_cpus = job_info.cpus;
-        let mut rest_ram = job_info.ram;
-        let mut scheduled_jobs: Vec<JobRequest> = Vec::new();
-        for (i, agent) in rec.iter().enumerate() {
-            scheduled_jobs.push(JobRequest {
-                agent_guid: agent.guid.clone(),
+        let scheduled_jobs: Vec<JobRequest> = placements
+            .into_iter()
+            .enumerate()
+            .map(|(i, placement)| JobRequest {
+                agent_guid: placement.agent_guid,
                 request: JobCreateRequest {
                     job_guid: job_info.guid.clone(),
                     image: job_info.image.clone(),
                     idx: i as u64,
-                    cpus: std::cmp::min(rest_cpus, agent.free_cpus.unwrap_or(0) as u64),
-                    ram: std::cmp::min(rest_ram, agent.free_ram.unwrap_or(0) as u64),
+                    cpus: placement.cpus,
+                    ram: placement.ram,
                     timeout: job_info.timeout.clone(),
                     target: job_info.target.clone(),
                     corpus: job_info.corpus.clone(),
                     last_msg: "".to_string(),
                     status: "init".to_string(),
                     crash_auto_analyze: job_info.crash_auto_analyze,
+                    env: job_info.env.clone().into_iter().collect(),
+                    fuzzer: Some((&job_info.fuzzer).into()),
                 },
-            });
-            rest_cpus -= std::cmp::min(rest_cpus, agent.free_cpus.unwrap_or(0) as u64);
-            rest_ram -= std::cmp::min(rest_ram, agent.free_ram.unwrap_or(0) as u64);
-        }
+            })
+            .collect();
 
         for job in scheduled_jobs.iter() {
             let cpus = i64::try_from(job.request.cpus)?;
@@ -162,7 +267,7 @@ impl Job {
                 cpus,
                 ram
             )
-            .execute(&mut tx)
+            .execute(&mut *tx)
             .await?;
 
             sqlx::query!(
@@ -178,17 +283,76 @@ impl Job {
                 "",
                 "init"
             )
-            .execute(&mut tx)
+            .execute(&mut *tx)
             .await?;
         }
 
+        Ok(Some(scheduled_jobs))
+    }
+
+    /// Schedules the job right away if the agents have enough free resources
+    /// and no job is waiting ahead of it, otherwise puts it into the queue.
+    /// Returns no sub-jobs for the queued job
+    pub async fn schedule_job(job_info: &JobInfo, pool: &SqlitePool) -> Result<Vec<JobRequest>> {
+        let mut tx = pool.begin().await?;
+
+        let labels = Agent::get_all_labels(&mut tx).await?;
+        let capacity: u64 = sqlx::query!(
+            r#"
+            SELECT guid, cpus
+            FROM agents
+            WHERE agent_type = $1
+            "#,
+            job_info.agent_type
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .filter(|rec| job_info.strategy != "pin" || job_info.pinned_agents.contains(&rec.guid))
+        .filter(|rec| satisfies(job_info, labels.get(&rec.guid).unwrap_or(&Labels::new())))
+        .map(|rec| rec.cpus.unwrap_or(0) as u64)
+        .sum();
+        if capacity < job_info.cpus {
+            return Err(anyhow::anyhow!(
+                "Insufficient amount of cpus on {} agents matching the job",
+                job_info.agent_type
+            ));
+        }
+
+        let ahead = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM job_collection
+            WHERE status = 'queued' AND agent_type = $1 AND priority >= $2
+            "#,
+            job_info.agent_type,
+            job_info.priority
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        let scheduled_jobs = if ahead.count == 0 {
+            Self::schedule_sub_jobs(job_info, &mut tx).await?
+        } else {
+            None
+        };
+        let status = match scheduled_jobs {
+            Some(_) => "init",
+            None => "queued",
+        };
+
         let cpus = i64::try_from(job_info.cpus)?;
         let ram = i64::try_from(job_info.ram)?;
+        let min_cores = i64::try_from(job_info.min_cores)?;
+        let pinned_agents = job_info.pinned_agents.join(",");
+        let constraints = format_labels(&job_info.constraints);
+        let env = serde_json::to_string(&job_info.env)?;
+        let fuzzer = serde_json::to_string(&job_info.fuzzer)?;
         let now = chrono::offset::Utc::now().to_string();
         sqlx::query!(
             r#"
-            INSERT INTO job_collection (guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status, crash_auto_analyze)
-            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            INSERT INTO job_collection (guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status, crash_auto_analyze, created_by, priority, strategy, min_cores, pinned_agents, constraints, env, fuzzer)
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)
             "#,
             job_info.guid,
             job_info.name,
@@ -201,16 +365,101 @@ impl Job {
             job_info.timeout,
             job_info.target,
             job_info.corpus,
-            "init",
-            job_info.crash_auto_analyze
+            status,
+            job_info.crash_auto_analyze,
+            job_info.created_by,
+            job_info.priority,
+            job_info.strategy,
+            min_cores,
+            pinned_agents,
+            constraints,
+            env,
+            fuzzer
         )
         .execute(&mut tx)
         .await?;
 
+        Artifact::add_refs(&job_info.guid, &job_info.artifacts, &mut tx).await?;
+
         tx.commit().await.unwrap();
+        Ok(scheduled_jobs.unwrap_or_default())
+    }
+
+    /// Places the queued jobs in the order of their priority while the agents
+    /// have enough free resources. A job which doesn't fit blocks the queue of
+    /// its agent type, so the large jobs don't starve
+    pub async fn schedule_queued(pool: &SqlitePool) -> Result<Vec<JobRequest>> {
+        let queued = sqlx::query!(
+            r#"
+            SELECT guid
+            FROM job_collection
+            WHERE status = 'queued'
+            ORDER BY priority DESC, creation_date ASC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut blocked = HashSet::new();
+        let mut scheduled_jobs = Vec::new();
+        for rec in queued {
+            let job_info = Self::get_job_info(&rec.guid, pool).await?;
+            if blocked.contains(&job_info.agent_type) {
+                continue;
+            }
+
+            let mut tx = pool.begin().await?;
+            match Self::schedule_sub_jobs(&job_info, &mut tx).await? {
+                Some(jobs) => {
+                    // The job might have been cancelled since it was read
+                    let rows_affected = sqlx::query!(
+                        r#"
+                        UPDATE job_collection
+                        SET status = 'init'
+                        WHERE guid = $1 AND status = 'queued'
+                        "#,
+                        job_info.guid
+                    )
+                    .execute(&mut tx)
+                    .await?
+                    .rows_affected();
+                    if rows_affected != 1 {
+                        tx.rollback().await?;
+                        continue;
+                    }
+                    tx.commit().await?;
+
+                    info!("Scheduled queued job {}", job_info.guid);
+                    scheduled_jobs.extend(jobs);
+                }
+                None => {
+                    tx.rollback().await?;
+                    blocked.insert(job_info.agent_type);
+                }
+            }
+        }
+
         Ok(scheduled_jobs)
     }
 
+    /// Takes the job out of the queue before it has been scheduled. Returns
+    /// false when the job has already left the queue
+    pub async fn cancel_queued(guid: &str, pool: &SqlitePool) -> Result<bool> {
+        let rows_affected = sqlx::query!(
+            r#"
+            UPDATE job_collection
+            SET status = 'cancelled'
+            WHERE guid = $1 AND status = 'queued'
+            "#,
+            guid
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
+    }
+
     pub async fn get_job_stats(pool: &SqlitePool) -> Result<JobStats> {
         let rec = sqlx::query!(
             r#"
@@ -236,6 +485,7 @@ impl Job {
             match stat.status.as_ref() {
                 "alive" => job_stats.alive += stat.count.unwrap() as u64,
                 "init" => job_stats.alive += stat.count.unwrap() as u64,
+                "paused" => job_stats.alive += stat.count.unwrap() as u64,
                 "completed" => job_stats.completed = stat.count.unwrap() as u64,
                 "error" => job_stats.error = stat.count.unwrap() as u64,
                 _ => {}
@@ -248,7 +498,7 @@ impl Job {
     pub async fn get_job(guid: &str, pool: &SqlitePool) -> Result<JobInfoResponse> {
         let rec = sqlx::query!(
             "
-            SELECT guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status
+            SELECT guid, name, description, creation_date, agent_type, image, cpus, ram, timeout, target, corpus, status, created_by, priority, env, fuzzer
             FROM job_collection
             WHERE guid = $1
             ",
@@ -257,7 +507,7 @@ impl Job {
         .fetch_one(pool)
         .await?;
 
-        let job_collection = JobCollection {
+        let mut job_collection = JobCollection {
             guid: rec.guid,
             name: rec.name,
             description: rec.description,
@@ -270,8 +520,30 @@ impl Job {
             target: rec.target,
             corpus: rec.corpus,
             status: rec.status,
+            created_by: rec.created_by,
+            priority: rec.priority,
+            queue_position: None,
+            env: serde_json::from_str(&rec.env).unwrap_or_default(),
+            fuzzer: serde_json::from_str(&rec.fuzzer).unwrap_or_default(),
         };
 
+        if job_collection.status == "queued" {
+            let rec = sqlx::query!(
+                r#"
+                SELECT COUNT(*) as "ahead!: i64"
+                FROM job_collection
+                WHERE status = 'queued' AND agent_type = $1
+                    AND (priority > $2 OR (priority = $2 AND creation_date < $3))
+                "#,
+                job_collection.agent_type,
+                job_collection.priority,
+                job_collection.creation_date
+            )
+            .fetch_one(pool)
+            .await?;
+            job_collection.queue_position = Some(rec.ahead as u64 + 1);
+        }
+
         let jobs = sqlx::query!(
             "
             SELECT agent_guid, collection_guid, idx, cpus, ram, last_msg, log, status
@@ -309,6 +581,7 @@ impl Job {
             SELECT status, COUNT(*) as count
             FROM jobs
             WHERE collection_guid = $1
+            GROUP BY status
             "#,
             job_guid
         )
@@ -319,10 +592,12 @@ impl Job {
         let mut alive = 0;
         // let mut completed = 0;
         let mut init = 0;
+        let mut paused = 0;
 
         for stat in statuses.iter() {
             match stat.status.as_ref() {
                 "alive" => alive = stat.count.unwrap() as u64,
+                "paused" => paused = stat.count.unwrap() as u64,
                 "init" => init = stat.count.unwrap() as u64 as u64,
                 // "completed" => completed = stat.count.unwrap() as u64,
                 "error" => errors = stat.count.unwrap() as u64,
@@ -337,7 +612,10 @@ impl Job {
             status = "init";
         } else if alive != 0 {
             status = "alive";
+        } else if paused != 0 {
+            status = "paused";
         } else {
+            // Sub-jobs stopped by their timeout have finished successfully
             status = "completed";
         }
 
@@ -363,6 +641,8 @@ impl Job {
         status: &str,
         pool: &SqlitePool,
     ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
         sqlx::query!(
             r#"
             UPDATE jobs
@@ -373,9 +653,17 @@ impl Job {
             agent_guid,
             status
         )
-        .execute(pool)
+        .execute(&mut tx)
         .await?;
 
+        // Paused sub-jobs give their cpus back until they get resumed, a paused
+        // container still holds its memory
+        if status == "paused" {
+            Self::free_cpus(agent_guid, job_guid, &mut tx).await?;
+        }
+
+        tx.commit().await?;
+
         Self::propagate_status(job_guid, pool).await?;
 
         Ok(())
@@ -425,43 +713,87 @@ impl Job {
         Ok(())
     }
 
-    pub async fn complete_job(
+    /// Returns the cpus of the paused sub-job to its agent, the ram stays
+    /// reserved. A paused sub-job with freed set holds only its ram
+    async fn free_cpus(
         agent_guid: &str,
         job_guid: &str,
-        last_msg: &str,
-        status: &str,
-        pool: &SqlitePool,
+        tx: &mut Transaction<'_, Sqlite>,
     ) -> Result<()> {
-        let mut tx = pool.begin().await?;
-
         let rec = sqlx::query!(
             r#"
-            SELECT id, agent_guid, cpus, ram
+            SELECT id, agent_guid, cpus
             FROM jobs
             WHERE collection_guid = $1 AND agent_guid = $2 AND freed != 1
             "#,
             job_guid,
             agent_guid
         )
-        .fetch_all(&mut tx)
+        .fetch_all(&mut *tx)
         .await?;
 
-        if rec.is_empty() {
-            return Ok(());
+        for job in rec {
+            sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET freed = 1
+                WHERE id = $1
+                "#,
+                job.id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                UPDATE agents
+                SET free_cpus = free_cpus + $1
+                WHERE guid = $2
+                "#,
+                job.cpus,
+                job.agent_guid
+            )
+            .execute(&mut *tx)
+            .await?;
         }
 
-        if let Some(job) = rec.get(0) {
+        Ok(())
+    }
+
+    /// Returns the resources of the sub-job to its agent unless they have
+    /// already been returned. Has to run before the status of a paused
+    /// sub-job changes, its ram is still reserved
+    async fn free_resources(
+        agent_guid: &str,
+        job_guid: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> Result<()> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT id, agent_guid, cpus, ram, status, freed
+            FROM jobs
+            WHERE collection_guid = $1 AND agent_guid = $2
+                AND (freed != 1 OR status = 'paused')
+            "#,
+            job_guid,
+            agent_guid
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for job in rec {
+            // The cpus of a paused sub-job have been returned when it got paused
+            let cpus = if job.freed { 0 } else { job.cpus };
+
             sqlx::query!(
                 r#"
                 UPDATE jobs
-                SET freed = 1, last_msg = $2, status = $3
+                SET freed = 1
                 WHERE id = $1
                 "#,
-                job.id,
-                last_msg,
-                status
+                job.id
             )
-            .execute(&mut tx)
+            .execute(&mut *tx)
             .await?;
 
             sqlx::query!(
@@ -470,20 +802,324 @@ impl Job {
                 SET free_cpus = free_cpus + $1, free_ram = free_ram + $2
                 WHERE guid = $3
                 "#,
-                job.cpus,
+                cpus,
                 job.ram,
                 job.agent_guid
             )
-            .execute(&mut tx)
+            .execute(&mut *tx)
             .await?;
         }
 
+        Ok(())
+    }
+
+    pub async fn complete_job(
+        agent_guid: &str,
+        job_guid: &str,
+        last_msg: &str,
+        status: &str,
+        pool: &SqlitePool,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        // Freed while the sub-job still reads as paused, it holds only its ram
+        // then
+        Self::free_resources(agent_guid, job_guid, &mut tx).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET last_msg = $3, status = $4
+            WHERE collection_guid = $1 AND agent_guid = $2
+            "#,
+            job_guid,
+            agent_guid,
+            last_msg,
+            status
+        )
+        .execute(&mut tx)
+        .await?;
+
         tx.commit().await.unwrap();
 
         Self::propagate_status(job_guid, pool).await?;
         Ok(())
     }
 
+    /// Takes the cpus of the paused sub-jobs back before resuming them, their
+    /// ram has stayed reserved. Returns the agents the sub-jobs are running on
+    pub async fn reserve_paused(guid: &str, pool: &SqlitePool) -> Result<Vec<String>> {
+        let mut tx = pool.begin().await?;
+
+        let rec = sqlx::query!(
+            r#"
+            SELECT jobs.id, jobs.agent_guid, jobs.cpus, agents.free_cpus
+            FROM jobs
+            INNER JOIN agents ON agents.guid = jobs.agent_guid
+            WHERE jobs.collection_guid = $1 AND jobs.status = 'paused' AND jobs.freed = 1
+            "#,
+            guid
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut agents = Vec::new();
+        for job in rec {
+            if job.free_cpus.unwrap_or(0) < job.cpus {
+                return Err(anyhow::anyhow!(
+                    "Insufficient resources on agent {} to resume the job",
+                    job.agent_guid
+                ));
+            }
+
+            sqlx::query!(
+                r#"
+                UPDATE agents
+                SET free_cpus = free_cpus - $2
+                WHERE guid = $1
+                "#,
+                job.agent_guid,
+                job.cpus
+            )
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET freed = 0
+                WHERE id = $1
+                "#,
+                job.id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            agents.push(job.agent_guid);
+        }
+
+        tx.commit().await?;
+        Ok(agents)
+    }
+
+    pub async fn get_job_info(guid: &str, pool: &SqlitePool) -> Result<JobInfo> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT guid, name, description, agent_type, image, cpus, ram, timeout, target, corpus, crash_auto_analyze, created_by, priority, strategy, min_cores, pinned_agents, constraints, env, fuzzer
+            FROM job_collection
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(JobInfo {
+            guid: rec.guid,
+            name: rec.name,
+            description: rec.description,
+            agent_type: rec.agent_type,
+            image: rec.image,
+            cpus: rec.cpus.unwrap_or(0) as u64,
+            ram: rec.ram.unwrap_or(0) as u64,
+            timeout: rec.timeout,
+            target: rec.target,
+            corpus: rec.corpus,
+            crash_auto_analyze: rec.crash_auto_analyze,
+            created_by: rec.created_by,
+            priority: rec.priority,
+            strategy: rec.strategy,
+            min_cores: rec.min_cores as u64,
+            pinned_agents: rec
+                .pinned_agents
+                .split(',')
+                .filter(|guid| !guid.is_empty())
+                .map(|guid| guid.to_string())
+                .collect(),
+            constraints: parse_labels(&rec.constraints).unwrap_or_default(),
+            env: serde_json::from_str(&rec.env).unwrap_or_default(),
+            fuzzer: serde_json::from_str(&rec.fuzzer).unwrap_or_default(),
+            artifacts: Artifact::get_refs(guid, pool).await?,
+        })
+    }
+
+    /// Schedules the sub-jobs of a finished job collection once again. The
+    /// findings synced in res/ are picked up by schedule.py as a part of
+    /// the corpus
+    pub async fn restart_job(guid: &str, pool: &SqlitePool) -> Result<Vec<JobRequest>> {
+        let job_info = Self::get_job_info(guid, pool).await?;
+
+        let mut tx = pool.begin().await?;
+
+        let rec = sqlx::query!(
+            r#"
+            SELECT status
+            FROM job_collection
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        if rec.status == "init"
+            || rec.status == "alive"
+            || rec.status == "paused"
+            || rec.status == "queued"
+        {
+            return Err(anyhow::anyhow!("Job is still running"));
+        }
+
+        // The collection reports an error as soon as one of the sub-jobs fails,
+        // the others may still hold their containers and resources
+        let running = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM jobs
+            WHERE collection_guid = $1
+                AND (freed = 0 OR status IN ('init', 'alive', 'paused'))
+            "#,
+            guid
+        )
+        .fetch_one(&mut tx)
+        .await?
+        .count;
+
+        if running > 0 {
+            return Err(anyhow::anyhow!("Some of the sub-jobs are still running"));
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM jobs
+            WHERE collection_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let scheduled_jobs = Self::schedule_sub_jobs(&job_info, &mut tx).await?;
+        let status = match scheduled_jobs {
+            Some(_) => "init",
+            None => "queued",
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE job_collection
+            SET status = $2
+            WHERE guid = $1
+            "#,
+            guid,
+            status
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(scheduled_jobs.unwrap_or_default())
+    }
+
+    /// Removes the job collection together with its sub-jobs and crashes.
+    /// Returns the agents which have run the sub-jobs
+    pub async fn delete_job(guid: &str, pool: &SqlitePool) -> Result<Vec<String>> {
+        let mut tx = pool.begin().await?;
+
+        let agents = sqlx::query!(
+            r#"
+            SELECT DISTINCT agent_guid
+            FROM jobs
+            WHERE collection_guid = $1
+            "#,
+            guid
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|rec| rec.agent_guid)
+        .collect::<Vec<String>>();
+
+        for agent_guid in agents.iter() {
+            Self::free_resources(agent_guid, guid, &mut tx).await?;
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE buckets
+            SET hits = hits - (
+                SELECT COUNT(*)
+                FROM crashes
+                WHERE crashes.bucket_id = buckets.id AND crashes.collection_guid = $1
+            )
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM crashes
+            WHERE collection_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM buckets
+            WHERE id NOT IN (SELECT bucket_id FROM crashes WHERE bucket_id IS NOT NULL)
+            "#
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM jobs
+            WHERE collection_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        Artifact::remove_refs(guid, &mut tx).await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM job_collection
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(agents)
+    }
+
+    /// Returns the finished job collections created before the cutoff
+    pub async fn get_expired(cutoff: &str, pool: &SqlitePool) -> Result<Vec<String>> {
+        Ok(sqlx::query!(
+            r#"
+            SELECT guid
+            FROM job_collection
+            WHERE status IN ('completed', 'error', 'cancelled') AND creation_date < $1
+            "#,
+            cutoff
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|rec| rec.guid)
+        .collect())
+    }
+
     pub async fn sync_jobs(
         agent_guid: &str,
         jobs: JobInfoContainerList,
@@ -499,7 +1135,7 @@ impl Job {
             r#"
             SELECT collection_guid
             FROM jobs
-            WHERE status IN ("init", "alive") AND agent_guid == ? AND collection_guid NOT IN ({})
+            WHERE status IN ("init", "alive", "paused") AND agent_guid == ? AND collection_guid NOT IN ({})
             "#,
             (0..collection_guids.len())
                 .map(|_| "?")
@@ -530,7 +1166,7 @@ impl Job {
         }
 
         for job in jobs.jobs.iter() {
-            if job.status == "error" || job.status == "completed" {
+            if job.status == "error" || job.status == "completed" || job.status == "timeout" {
                 Self::complete_job(
                     agent_guid,
                     &job.job_guid,
@@ -540,19 +1176,8 @@ impl Job {
                 )
                 .await?;
             } else {
-                sqlx::query!(
-                    r#"
-                    UPDATE jobs
-                    SET status = $3, last_msg = $4
-                    WHERE agent_guid = $1 AND collection_guid = $2
-                    "#,
-                    agent_guid,
-                    job.job_guid,
-                    job.status,
-                    job.last_msg
-                )
-                .execute(pool)
-                .await?;
+                Self::set_job_last_msg(agent_guid, &job.job_guid, &job.last_msg, pool).await?;
+                Self::set_job_status(agent_guid, &job.job_guid, &job.status, pool).await?;
             }
         }
 
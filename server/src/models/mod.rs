@@ -1,7 +1,15 @@
 mod agent;
+mod artifact;
+mod bucket;
+mod corpus;
 mod crash;
 mod job;
+mod user;
 
 pub use agent::*;
+pub use artifact::*;
+pub use bucket::*;
+pub use corpus::*;
 pub use crash::*;
 pub use job::*;
+pub use user::*;
@@ -0,0 +1,299 @@
+use std::fmt;
+
+use actix_http::body::BoxBody;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use anyhow::Result;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+pub const SESSION_TTL: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "operator" => Some(Role::Operator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserCreateRequest {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenCreateRequest {
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub guid: String,
+    pub username: String,
+    pub role: Role,
+    pub creation_date: String,
+}
+
+impl Responder for User {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::Ok().json(&self)
+    }
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha3_256::digest(token.as_bytes()))
+}
+
+pub(crate) fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+impl User {
+    pub async fn get_all(pool: &SqlitePool) -> Result<Vec<User>> {
+        let users = sqlx::query!(
+            r#"
+            SELECT guid, username, role, creation_date
+            FROM users
+            ORDER BY username
+            "#
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|rec| {
+            Some(User {
+                guid: rec.guid,
+                username: rec.username,
+                role: Role::parse(&rec.role)?,
+                creation_date: rec.creation_date,
+            })
+        })
+        .collect();
+
+        Ok(users)
+    }
+
+    pub async fn count(pool: &SqlitePool) -> Result<i64> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as count
+            FROM users
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(rec.count as i64)
+    }
+
+    pub async fn create(req: &UserCreateRequest, pool: &SqlitePool) -> Result<User> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(req.password.as_bytes(), &salt)
+            .map_err(|err| anyhow::anyhow!("failed to hash password: {}", err))?
+            .to_string();
+
+        let user = User {
+            guid: Uuid::new_v4().to_string(),
+            username: req.username.clone(),
+            role: req.role,
+            creation_date: chrono::offset::Utc::now().to_string(),
+        };
+        let role = user.role.to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO users (guid, username, password_hash, role, creation_date)
+            VALUES($1, $2, $3, $4, $5)
+            "#,
+            user.guid,
+            user.username,
+            password_hash,
+            role,
+            user.creation_date
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn delete(guid: String, pool: &SqlitePool) -> Result<String> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM tokens
+            WHERE user_guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE guid = $1
+            "#,
+            guid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(guid)
+    }
+
+    pub async fn authenticate(req: &LoginRequest, pool: &SqlitePool) -> Result<Option<User>> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT guid, username, password_hash, role, creation_date
+            FROM users
+            WHERE username = $1
+            "#,
+            req.username
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return Ok(None),
+        };
+
+        let password_hash = PasswordHash::new(&rec.password_hash)
+            .map_err(|err| anyhow::anyhow!("invalid password hash: {}", err))?;
+        if Argon2::default()
+            .verify_password(req.password.as_bytes(), &password_hash)
+            .is_err()
+        {
+            return Ok(None);
+        }
+
+        Ok(Role::parse(&rec.role).map(|role| User {
+            guid: rec.guid,
+            username: rec.username,
+            role,
+            creation_date: rec.creation_date,
+        }))
+    }
+
+    /// Issues a new token for the user, sessions expire after SESSION_TTL
+    /// while API tokens live until the user gets deleted
+    pub async fn issue_token(
+        &self,
+        kind: &str,
+        description: &str,
+        pool: &SqlitePool,
+    ) -> Result<String> {
+        let token = new_token();
+        let hash = hash_token(&token);
+        let now = chrono::offset::Utc::now();
+        let creation_date = now.to_string();
+        let expires = match kind {
+            "session" => Some(now.timestamp() + SESSION_TTL),
+            _ => None,
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tokens (hash, user_guid, kind, description, creation_date, expires)
+            VALUES($1, $2, $3, $4, $5, $6)
+            "#,
+            hash,
+            self.guid,
+            kind,
+            description,
+            creation_date,
+            expires
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    pub async fn revoke_token(token: &str, pool: &SqlitePool) -> Result<()> {
+        let hash = hash_token(token);
+        sqlx::query!(
+            r#"
+            DELETE FROM tokens
+            WHERE hash = $1
+            "#,
+            hash
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_by_token(token: &str, pool: &SqlitePool) -> Result<Option<User>> {
+        let hash = hash_token(token);
+        let now = chrono::offset::Utc::now().timestamp();
+        let rec = sqlx::query!(
+            r#"
+            SELECT users.guid, users.username, users.role, users.creation_date
+            FROM tokens
+            INNER JOIN users ON users.guid = tokens.user_guid
+            WHERE tokens.hash = $1 AND (tokens.expires IS NULL OR tokens.expires > $2)
+            "#,
+            hash,
+            now
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(rec.and_then(|rec| {
+            Some(User {
+                guid: rec.guid,
+                username: rec.username,
+                role: Role::parse(&rec.role)?,
+                creation_date: rec.creation_date,
+            })
+        }))
+    }
+}
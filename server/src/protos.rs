@@ -13,6 +13,8 @@ use agent::{Empty, JobGuid, JobsList, SysInfo, Update};
 #[allow(unused_imports)]
 use agent::update::UpdateKind::CrashMsg;
 
+// The generated update kinds all end with Msg
+#[allow(clippy::enum_variant_names)]
 pub mod agent {
     tonic::include_proto!("agent");
 }
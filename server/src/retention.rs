@@ -0,0 +1,121 @@
+use std::{fs, io, path::Path, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use log::{error, info};
+use sqlx::SqlitePool;
+use tokio::{sync::mpsc::Sender, time};
+
+use crate::artifact::remove_artifact;
+use crate::broker::{Event, Request};
+use crate::config::CONFIG;
+use crate::models::{Artifact, Job};
+use crate::utils::{get_job_dir, notify_processor};
+
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Uploaded artifacts are kept this long for the jobs to refer to them
+const ARTIFACT_GRACE_HOURS: i64 = 24;
+
+/// Removes the job directory from the NFS, the crashes can be kept in
+/// NFS_DIR/archive/<guid>
+fn remove_job_dir(job_guid: &str, keep_crashes: bool) -> io::Result<()> {
+    let job_dir = get_job_dir(job_guid);
+    if !job_dir.exists() {
+        return Ok(());
+    }
+
+    let crashes_dir = job_dir.join("crashes");
+    if keep_crashes && crashes_dir.exists() {
+        let archive_dir = Path::new(&CONFIG.nfs_dir).join("archive");
+        fs::create_dir_all(&archive_dir)?;
+        fs::rename(crashes_dir, archive_dir.join(job_guid))?;
+    }
+
+    fs::remove_dir_all(job_dir)
+}
+
+/// Removes the job from the database, the agents and the NFS. The agents
+/// stop the sub-jobs which are still running
+pub async fn purge_job(
+    guid: &str,
+    keep_crashes: bool,
+    db_pool: &SqlitePool,
+    tx: &Arc<Sender<Event>>,
+) -> Result<()> {
+    let agents = Job::delete_job(guid, db_pool).await?;
+
+    for agent_guid in agents {
+        notify_processor(
+            tx,
+            Event::AgentRequest {
+                guid: agent_guid,
+                request: Box::new(Request::JobDestroy {
+                    guid: guid.to_string(),
+                }),
+            },
+        )
+        .await;
+    }
+
+    notify_processor(tx, Event::ScheduleQueued).await;
+
+    remove_job_dir(guid, keep_crashes)?;
+    info!("Purged job {}", guid);
+
+    Ok(())
+}
+
+/// Periodically purges the finished jobs older than JOB_RETENTION_DAYS
+pub async fn retention(db_pool: SqlitePool, tx: Sender<Event>) {
+    let days = match CONFIG.job_retention_days {
+        Some(days) => days,
+        None => return,
+    };
+
+    let tx = Arc::new(tx);
+    let mut interval = time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let cutoff = (chrono::offset::Utc::now() - chrono::Duration::days(days as i64)).to_string();
+        let expired = match Job::get_expired(&cutoff, &db_pool).await {
+            Ok(expired) => expired,
+            Err(err) => {
+                error!("Failed to fetch expired jobs: {}", err);
+                continue;
+            }
+        };
+
+        for guid in expired {
+            if let Err(err) =
+                purge_job(&guid, CONFIG.job_retention_keep_crashes, &db_pool, &tx).await
+            {
+                error!("Failed to purge job {}: {}", guid, err);
+            }
+        }
+    }
+}
+
+/// Periodically removes the artifacts no job refers to anymore
+pub async fn collect_artifacts(db_pool: SqlitePool) {
+    let mut interval = time::interval(RETENTION_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let cutoff = (chrono::offset::Utc::now() - chrono::Duration::hours(ARTIFACT_GRACE_HOURS))
+            .to_string();
+        let unused = match Artifact::collect(&cutoff, &db_pool).await {
+            Ok(unused) => unused,
+            Err(err) => {
+                error!("Failed to collect artifacts: {}", err);
+                continue;
+            }
+        };
+
+        for hash in unused {
+            match remove_artifact(&hash) {
+                Ok(_) => info!("Removed unused artifact {}", hash),
+                Err(err) => error!("Failed to remove artifact {}: {}", hash, err),
+            }
+        }
+    }
+}
@@ -1,9 +1,20 @@
 use crate::handlers::{
-    agent::{create, delete, get_all, get_by_guid},
-    crash::{get_crash, get_crash_info, get_crash_stats, get_crashes},
-    job::{create_job, get_job, get_job_crashes, get_job_stats, get_jobs, stop_job},
+    agent::{create, delete, get_all, get_by_guid, heartbeat, register, set_labels, set_state},
+    artifact::{get_artifacts, upload_artifact},
+    bucket::{get_bucket, get_buckets},
+    corpus::{delete_corpus, get_corpora, get_corpus, upload_corpus},
+    crash::{
+        analyze_crash, get_crash, get_crash_info, get_crash_stats, get_crashes,
+        get_minimized_crash, minimize_crash,
+    },
+    job::{
+        clone_job, create_job, create_job_from_spec, delete_job, get_job, get_job_corpus,
+        get_job_crashes, get_job_spec, get_job_spec_schema, get_job_stats, get_jobs, pause_job,
+        restart_job, resume_job, save_job_corpus, stop_job, stream_job_log,
+    },
     stats::{query_job_stats, query_stats},
-    web::{agents, crash, crashes, index, job, jobs},
+    user::{create_token, create_user, delete_user, get_users, login, logout, whoami},
+    web::{agents, bucket, buckets, crash, crashes, index, job, jobs, login_page},
 };
 
 use actix_files::Files;
@@ -17,23 +28,60 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
                 // AGENT routes
                 .service(get_all)
                 .service(get_by_guid)
+                .service(set_labels)
+                .service(set_state)
+                .service(register)
+                .service(heartbeat)
                 .service(create)
                 .service(delete)
                 // JOB routes
                 .service(create_job)
+                .service(create_job_from_spec)
+                .service(get_job_spec_schema)
                 .service(get_job_stats)
                 .service(get_jobs)
                 .service(get_job)
+                .service(delete_job)
                 .service(stop_job)
+                .service(pause_job)
+                .service(resume_job)
+                .service(restart_job)
+                .service(clone_job)
                 .service(get_job_crashes)
+                .service(get_job_corpus)
+                .service(save_job_corpus)
+                .service(get_job_spec)
+                .service(stream_job_log)
+                // ARTIFACT routes
+                .service(get_artifacts)
+                .service(upload_artifact)
+                // CORPUS routes
+                .service(get_corpora)
+                .service(get_corpus)
+                .service(upload_corpus)
+                .service(delete_corpus)
                 // CRASH routes
                 .service(get_crashes)
                 .service(get_crash_stats)
                 .service(get_crash_info)
                 .service(get_crash)
+                .service(analyze_crash)
+                .service(minimize_crash)
+                .service(get_minimized_crash)
+                // BUCKET routes
+                .service(get_buckets)
+                .service(get_bucket)
                 // SATS routes
                 .service(query_stats)
-                .service(query_job_stats),
+                .service(query_job_stats)
+                // USER routes
+                .service(login)
+                .service(logout)
+                .service(whoami)
+                .service(create_token)
+                .service(get_users)
+                .service(create_user)
+                .service(delete_user),
         )
         // WEB routes
         .service(index)
@@ -42,6 +90,9 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
         .service(job)
         .service(crashes)
         .service(crash)
+        .service(buckets)
+        .service(bucket)
+        .service(login_page)
         .service(
             Files::new("/static", "./static/")
                 .prefer_utf8(true)
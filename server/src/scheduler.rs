@@ -0,0 +1,318 @@
+use crate::handlers::agent::JobInfo;
+use crate::models::Labels;
+
+pub const DEFAULT_STRATEGY: &str = "binpack";
+
+/// Free resources of an agent which is up and matches the job
+#[derive(Debug, Clone)]
+pub struct AgentResources {
+    pub guid: String,
+    pub free_cpus: u64,
+    pub free_ram: u64,
+    pub labels: Labels,
+}
+
+/// Resources of a single sub-job
+#[derive(Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub agent_guid: String,
+    pub cpus: u64,
+    pub ram: u64,
+}
+
+/// Splits the job between the agents. Returns None when the agents don't
+/// have enough free resources right now
+pub trait Scheduler: Send + Sync {
+    fn place(&self, job_info: &JobInfo, agents: &[AgentResources]) -> Option<Vec<Placement>>;
+}
+
+/// Fills as few agents as possible, a job fitting a single agent goes to
+/// the fullest one so the large agents stay free for the large jobs
+pub struct BinPack;
+
+/// Distributes the cores evenly over as many agents as possible
+pub struct Spread;
+
+/// Spreads the job over the chosen agents only
+pub struct Pin;
+
+/// Returns the scheduler of the strategy, None for an unknown one
+pub fn get_scheduler(strategy: &str) -> Option<Box<dyn Scheduler>> {
+    match strategy {
+        "binpack" => Some(Box::new(BinPack)),
+        "spread" => Some(Box::new(Spread)),
+        "pin" => Some(Box::new(Pin)),
+        _ => None,
+    }
+}
+
+/// Checks the agent has all the labels the job is constrained to
+pub fn satisfies(job_info: &JobInfo, labels: &Labels) -> bool {
+    job_info
+        .constraints
+        .iter()
+        .all(|(key, value)| labels.get(key) == Some(value))
+}
+
+/// RAM each core of the job gets, 0 if the job isn't bound by RAM
+fn ram_per_core(job_info: &JobInfo) -> u64 {
+    if job_info.cpus == 0 {
+        return 0;
+    }
+    (job_info.ram + job_info.cpus - 1) / job_info.cpus
+}
+
+/// Amount of cores the agent can take, limited by its free RAM as well
+fn max_cores(job_info: &JobInfo, agent: &AgentResources) -> u64 {
+    match ram_per_core(job_info) {
+        0 => agent.free_cpus,
+        ram => std::cmp::min(agent.free_cpus, agent.free_ram / ram),
+    }
+}
+
+fn min_cores(job_info: &JobInfo) -> u64 {
+    std::cmp::max(1, std::cmp::min(job_info.min_cores, job_info.cpus))
+}
+
+/// Gives the RAM of the job to the sub-jobs proportionally to their cores
+fn with_ram(job_info: &JobInfo, cores: Vec<(String, u64)>) -> Vec<Placement> {
+    let ram_per_core = ram_per_core(job_info);
+    let mut rest_ram = job_info.ram;
+    cores
+        .into_iter()
+        .map(|(agent_guid, cpus)| {
+            let ram = std::cmp::min(cpus * ram_per_core, rest_ram);
+            rest_ram -= ram;
+            Placement {
+                agent_guid,
+                cpus,
+                ram,
+            }
+        })
+        .collect()
+}
+
+/// Agents matching the constraints of the job and able to run at least a
+/// minimal sub-job, the largest ones first
+fn candidates(job_info: &JobInfo, agents: &[AgentResources]) -> Vec<(String, u64)> {
+    let mut candidates: Vec<(String, u64)> = agents
+        .iter()
+        .filter(|agent| satisfies(job_info, &agent.labels))
+        .map(|agent| (agent.guid.clone(), max_cores(job_info, agent)))
+        .filter(|(_, cores)| *cores >= min_cores(job_info))
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1));
+    candidates
+}
+
+/// Takes as many cores as possible from every agent in turn, leaving enough
+/// cores for the last sub-job to get the minimum
+fn fill(job_info: &JobInfo, candidates: Vec<(String, u64)>) -> Option<Vec<(String, u64)>> {
+    let min_cores = min_cores(job_info);
+    let mut rest = job_info.cpus;
+    let mut cores = Vec::new();
+    for (guid, max_cores) in candidates {
+        if rest == 0 {
+            break;
+        }
+
+        let mut take = std::cmp::min(max_cores, rest);
+        let left = rest - take;
+        if left > 0 && left < min_cores {
+            take -= min_cores - left;
+        }
+        if take < min_cores {
+            continue;
+        }
+
+        cores.push((guid, take));
+        rest -= take;
+    }
+
+    if rest != 0 {
+        return None;
+    }
+    Some(cores)
+}
+
+/// Hands out the cores one by one to the least loaded agent
+fn level(job_info: &JobInfo, candidates: &[(String, u64)]) -> Option<Vec<(String, u64)>> {
+    let mut taken = vec![0; candidates.len()];
+    for _ in 0..job_info.cpus {
+        let next = (0..candidates.len())
+            .filter(|&i| taken[i] < candidates[i].1)
+            .min_by_key(|&i| taken[i])?;
+        taken[next] += 1;
+    }
+
+    if taken.iter().any(|cores| *cores < min_cores(job_info)) {
+        return None;
+    }
+    Some(
+        candidates
+            .iter()
+            .zip(taken)
+            .map(|((guid, _), cores)| (guid.clone(), cores))
+            .collect(),
+    )
+}
+
+impl Scheduler for BinPack {
+    fn place(&self, job_info: &JobInfo, agents: &[AgentResources]) -> Option<Vec<Placement>> {
+        let candidates = candidates(job_info, agents);
+
+        if let Some((guid, _)) = candidates
+            .iter()
+            .filter(|(_, cores)| *cores >= job_info.cpus)
+            .min_by_key(|(_, cores)| *cores)
+        {
+            return Some(with_ram(job_info, vec![(guid.clone(), job_info.cpus)]));
+        }
+
+        fill(job_info, candidates).map(|cores| with_ram(job_info, cores))
+    }
+}
+
+impl Scheduler for Spread {
+    fn place(&self, job_info: &JobInfo, agents: &[AgentResources]) -> Option<Vec<Placement>> {
+        let candidates = candidates(job_info, agents);
+
+        // Every sub-job has to get the minimum, so fewer agents are tried
+        // until the cores can be leveled between them
+        let most = std::cmp::min(candidates.len() as u64, job_info.cpus / min_cores(job_info));
+        (1..=most as usize)
+            .rev()
+            .find_map(|n| level(job_info, &candidates[..n]))
+            .map(|cores| with_ram(job_info, cores))
+    }
+}
+
+impl Scheduler for Pin {
+    fn place(&self, job_info: &JobInfo, agents: &[AgentResources]) -> Option<Vec<Placement>> {
+        let pinned: Vec<AgentResources> = agents
+            .iter()
+            .filter(|agent| job_info.pinned_agents.contains(&agent.guid))
+            .cloned()
+            .collect();
+        Spread.place(job_info, &pinned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::parse_labels;
+
+    fn job(cpus: u64, ram: u64, min_cores: u64) -> JobInfo {
+        JobInfo {
+            cpus,
+            ram,
+            min_cores,
+            ..Default::default()
+        }
+    }
+
+    fn agent(guid: &str, free_cpus: u64, free_ram: u64) -> AgentResources {
+        AgentResources {
+            guid: guid.to_string(),
+            free_cpus,
+            free_ram,
+            labels: Labels::new(),
+        }
+    }
+
+    fn cores(placements: Vec<Placement>) -> Vec<(String, u64)> {
+        placements
+            .into_iter()
+            .map(|placement| (placement.agent_guid, placement.cpus))
+            .collect()
+    }
+
+    #[test]
+    fn binpack_picks_the_fullest_agent_that_fits() {
+        let agents = [agent("a", 16, 0), agent("b", 4, 0), agent("c", 2, 0)];
+        let placements = BinPack.place(&job(3, 0, 1), &agents).unwrap();
+        assert_eq!(cores(placements), [("b".to_string(), 3)]);
+    }
+
+    #[test]
+    fn binpack_never_creates_empty_sub_jobs() {
+        let agents = [agent("a", 4, 0), agent("b", 4, 0), agent("c", 4, 0)];
+        let placements = BinPack.place(&job(6, 0, 1), &agents).unwrap();
+        assert_eq!(placements.len(), 2);
+        assert!(placements.iter().all(|placement| placement.cpus > 0));
+    }
+
+    #[test]
+    fn it_respects_ram() {
+        // 1024 per core, "a" can take only a single core because of its RAM
+        let agents = [agent("a", 8, 1024), agent("b", 2, 4096)];
+        let placements = BinPack.place(&job(3, 3072, 1), &agents).unwrap();
+        assert_eq!(
+            placements,
+            [
+                Placement {
+                    agent_guid: "b".to_string(),
+                    cpus: 2,
+                    ram: 2048
+                },
+                Placement {
+                    agent_guid: "a".to_string(),
+                    cpus: 1,
+                    ram: 1024
+                },
+            ]
+        );
+
+        assert!(BinPack.place(&job(4, 4096, 1), &agents).is_none());
+    }
+
+    #[test]
+    fn it_keeps_the_minimum_cores_per_agent() {
+        let agents = [agent("a", 5, 0), agent("b", 5, 0), agent("c", 1, 0)];
+        let placements = BinPack.place(&job(6, 0, 2), &agents).unwrap();
+        assert_eq!(
+            cores(placements),
+            [("a".to_string(), 4), ("b".to_string(), 2)]
+        );
+
+        assert!(BinPack.place(&job(11, 0, 2), &agents).is_none());
+    }
+
+    #[test]
+    fn spread_levels_the_cores() {
+        let agents = [agent("a", 8, 0), agent("b", 8, 0), agent("c", 1, 0)];
+        let placements = Spread.place(&job(7, 0, 2), &agents).unwrap();
+        assert_eq!(
+            cores(placements),
+            [("a".to_string(), 4), ("b".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn pin_uses_only_the_chosen_agents() {
+        let agents = [agent("a", 8, 0), agent("b", 2, 0)];
+        let mut job_info = job(2, 0, 1);
+        job_info.pinned_agents = vec!["b".to_string()];
+        let placements = Pin.place(&job_info, &agents).unwrap();
+        assert_eq!(cores(placements), [("b".to_string(), 2)]);
+
+        job_info.cpus = 3;
+        assert!(Pin.place(&job_info, &agents).is_none());
+    }
+
+    #[test]
+    fn it_honors_the_constraints() {
+        let mut arm = agent("arm", 2, 0);
+        arm.labels = parse_labels("arch=arm64, pool=nightly").unwrap();
+        let agents = [agent("x86", 16, 0), arm];
+
+        let mut job_info = job(2, 0, 1);
+        job_info.constraints = parse_labels("arch=arm64").unwrap();
+        let placements = BinPack.place(&job_info, &agents).unwrap();
+        assert_eq!(cores(placements), [("arm".to_string(), 2)]);
+
+        job_info.constraints = parse_labels("arch=arm64,pool=weekly").unwrap();
+        assert!(BinPack.place(&job_info, &agents).is_none());
+    }
+}
@@ -1,6 +1,8 @@
-use crate::broker::{broker, Event};
+use crate::auth::{bootstrap_admin, Authentication};
+use crate::broker::{broker, job_scheduler, Event};
 use crate::config::CONFIG;
 use crate::models::Agent;
+use crate::retention::{collect_artifacts, retention};
 use crate::routes::routes;
 
 use actix_web::{middleware::Logger, web, App, HttpServer};
@@ -30,16 +32,30 @@ pub async fn server() -> std::io::Result<()> {
     env_logger::init();
 
     let db_pool = SqlitePool::connect(&CONFIG.database_url).await.unwrap();
+    bootstrap_admin(&db_pool).await;
 
     let (tx, rx) = mpsc::channel::<Event>(100);
+    let (scheduler_tx, scheduler_rx) = mpsc::channel::<()>(1);
     let db = db_pool.clone();
-    tokio::spawn(async move { broker(db, rx).await });
+    tokio::spawn(async move { broker(db, rx, scheduler_tx).await });
     add_existing_agents(&tx, &db_pool).await;
 
+    let db = db_pool.clone();
+    let scheduler_events = tx.clone();
+    tokio::spawn(async move { job_scheduler(db, scheduler_rx, scheduler_events).await });
+
+    let db = db_pool.clone();
+    let retention_tx = tx.clone();
+    tokio::spawn(async move { retention(db, retention_tx).await });
+
+    let db = db_pool.clone();
+    tokio::spawn(async move { collect_artifacts(db).await });
+
     info!("Listening on {}", CONFIG.sap_server_listen);
 
     HttpServer::new(move || {
         App::new()
+            .wrap(Authentication)
             .wrap(Logger::default())
             .app_data(web::Data::new(db_pool.clone()))
             .app_data(web::Data::new(tx.clone()))
@@ -1,4 +1,5 @@
 use std::{
+    fs, io,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -20,3 +21,12 @@ pub async fn notify_processor<T: std::fmt::Debug>(tx: &Arc<Sender<T>>, agent_upd
 pub fn get_job_dir(job_guid: &str) -> PathBuf {
     Path::new(&CONFIG.nfs_dir).join("jobs").join(job_guid)
 }
+
+/// Hard links the file to be stored once, it is copied when the paths are
+/// on different file systems
+pub fn link_or_copy(src: &Path, dst: &Path) -> io::Result<()> {
+    if fs::hard_link(src, dst).is_err() {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
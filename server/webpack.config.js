@@ -10,7 +10,10 @@ module.exports = {
     jobs: './assets/js/jobs.js',
     job_page: './assets/js/job_page.js',
     crashes: './assets/js/crashes.js',
-    crash_page: './assets/js/crash_page.js'
+    crash_page: './assets/js/crash_page.js',
+    buckets: './assets/js/buckets.js',
+    bucket_page: './assets/js/bucket_page.js',
+    login: './assets/js/login.js'
   },
   plugins: [
     new webpack.ProvidePlugin({